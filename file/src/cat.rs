@@ -7,8 +7,6 @@
 // SPDX-License-Identifier: MIT
 //
 // TODO:
-// - Bug:  if stdout write_all() produces Err, the program will erroneously
-//   output the filename as the culprit, rather than the string "stdout"
 // - Questionable behavior:  if write_all() produces Err, the program will
 //   continue to the next file, rather than stopping.
 
@@ -17,42 +15,196 @@ extern crate plib;
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, textdomain};
+use plib::copy::{copy_fd, CopyError};
 use plib::PROJECT_NAME;
 use std::fs;
 use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
 
 /// cat - concatenate and print files
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about)]
 struct Args {
-    /// Disable output buffering (a no-op, for POSIX compat.)
-    #[arg(short, long, default_value_t = true)]
+    /// Write each block of input to standard output as soon as it is read.
+    #[arg(short, long)]
     unbuffered: bool,
 
+    /// Number all output lines.
+    #[arg(short, long)]
+    number: bool,
+
+    /// Number non-blank output lines; overrides -n.
+    #[arg(short = 'b', long)]
+    number_nonblank: bool,
+
+    /// Squeeze runs of blank lines into a single blank line.
+    #[arg(short, long)]
+    squeeze_blank: bool,
+
+    /// Show non-printing characters with ^ and M- notation, except for tabs and newlines.
+    #[arg(short = 'v', long)]
+    show_nonprinting: bool,
+
+    /// Show a $ at the end of each line.
+    #[arg(short = 'E', long)]
+    show_ends: bool,
+
+    /// Show tabs as ^I.
+    #[arg(short = 'T', long)]
+    show_tabs: bool,
+
+    /// Same as -vET.
+    #[arg(short = 'A', long)]
+    show_all: bool,
+
     /// Files to read as input.  Use "-" or no-args for stdin.
     files: Vec<String>,
 }
 
-fn cat_file(filename: &str) -> io::Result<()> {
+/// Line numbering and blank-line state, carried from one file to the
+/// next as if the files were one.
+struct LineState {
+    line_no: u64,
+    at_line_start: bool,
+    prev_blank: bool,
+}
+
+impl LineState {
+    fn new() -> LineState {
+        LineState {
+            line_no: 0,
+            at_line_start: true,
+            prev_blank: false,
+        }
+    }
+}
+
+fn displays_anything(args: &Args) -> bool {
+    args.number
+        || args.number_nonblank
+        || args.squeeze_blank
+        || args.show_nonprinting
+        || args.show_ends
+        || args.show_tabs
+}
+
+fn push_line_number(state: &mut LineState, out: &mut Vec<u8>) {
+    state.line_no += 1;
+    out.extend_from_slice(format!("{:6}\t", state.line_no).as_bytes());
+}
+
+/// Append a byte to `out`, in ^ and M- notation if it does not print.
+fn push_char(args: &Args, ch: u8, out: &mut Vec<u8>) {
+    if ch == b'\t' {
+        if args.show_tabs {
+            out.extend_from_slice(b"^I");
+        } else {
+            out.push(ch);
+        }
+        return;
+    }
+    if !args.show_nonprinting {
+        out.push(ch);
+        return;
+    }
+
+    let mut ch = ch;
+    if ch >= 128 {
+        out.extend_from_slice(b"M-");
+        ch -= 128;
+    }
+    if ch < 32 {
+        out.push(b'^');
+        out.push(ch + 64);
+    } else if ch == 127 {
+        out.extend_from_slice(b"^?");
+    } else {
+        out.push(ch);
+    }
+}
+
+/// Apply the display options to a block of input, appending the result
+/// to `out`.
+fn display_block(args: &Args, state: &mut LineState, buf: &[u8], out: &mut Vec<u8>) {
+    for &ch in buf {
+        if state.at_line_start {
+            if ch == b'\n' {
+                if args.squeeze_blank && state.prev_blank {
+                    continue;
+                }
+                state.prev_blank = true;
+                if args.number && !args.number_nonblank {
+                    push_line_number(state, out);
+                }
+            } else {
+                state.prev_blank = false;
+                if args.number || args.number_nonblank {
+                    push_line_number(state, out);
+                }
+                state.at_line_start = false;
+            }
+        }
+
+        if ch == b'\n' {
+            if args.show_ends {
+                out.push(b'$');
+            }
+            out.push(b'\n');
+            state.at_line_start = true;
+        } else {
+            push_char(args, ch, out);
+        }
+    }
+}
+
+/// Copy a file to stdout unchanged.  This goes straight between file
+/// descriptors, without the data passing through user space where the
+/// kernel allows it, so there is no buffering for -u to disable.
+fn copy_file(filename: &str) -> Result<(), CopyError> {
+    if filename == "-" {
+        copy_fd(io::stdin().as_raw_fd(), io::stdout().as_raw_fd())?;
+    } else {
+        let file = fs::File::open(filename).map_err(CopyError::Read)?;
+        copy_fd(file.as_raw_fd(), io::stdout().as_raw_fd())?;
+    }
+
+    Ok(())
+}
+
+fn cat_file(args: &Args, state: &mut LineState, filename: &str) -> Result<(), CopyError> {
+    if !displays_anything(args) {
+        return copy_file(filename);
+    }
+
     let mut file: Box<dyn Read>;
     if filename == "-" {
         file = Box::new(io::stdin().lock());
     } else {
-        file = Box::new(fs::File::open(filename)?);
+        file = Box::new(fs::File::open(filename).map_err(CopyError::Read)?);
     }
 
     let mut buffer = [0; plib::BUFSZ];
+    let mut outbuf = Vec::new();
+    let mut stdout = io::stdout().lock();
 
     loop {
-        let n_read = file.read(&mut buffer[..])?;
+        let n_read = file.read(&mut buffer[..]).map_err(CopyError::Read)?;
         if n_read == 0 {
             break;
         }
 
-        io::stdout().write_all(&buffer[0..n_read])?;
+        outbuf.clear();
+        display_block(args, state, &buffer[0..n_read], &mut outbuf);
+        stdout.write_all(&outbuf).map_err(CopyError::Write)?;
+
+        // standard output is line buffered; -u pushes out any
+        // partial line too
+        if args.unbuffered {
+            stdout.flush().map_err(CopyError::Write)?;
+        }
     }
 
-    Ok(())
+    stdout.flush().map_err(CopyError::Write)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -67,12 +219,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         args.files.push(String::from("-"));
     }
 
+    if args.show_all {
+        args.show_nonprinting = true;
+        args.show_ends = true;
+        args.show_tabs = true;
+    }
+
     let mut exit_code = 0;
+    let mut state = LineState::new();
 
     for filename in &args.files {
-        if let Err(e) = cat_file(filename) {
-            exit_code = 1;
-            eprintln!("{}: {}", filename, e);
+        match cat_file(&args, &mut state, filename) {
+            Ok(()) => {}
+            Err(CopyError::Read(e)) => {
+                exit_code = 1;
+                eprintln!("{}: {}", filename, e);
+            }
+            Err(CopyError::Write(e)) => {
+                exit_code = 1;
+                eprintln!("stdout: {}", e);
+            }
         }
     }
 
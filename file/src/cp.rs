@@ -7,17 +7,20 @@
 // SPDX-License-Identifier: MIT
 //
 // TODO:
-// - preserve file attributes during copy
 // - do not repeatedly stat(2) the target, for each source
 //
 
 extern crate atty;
 extern crate clap;
+extern crate libc;
 extern crate plib;
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
+use plib::fsnode::{copy_characteristics, make_special};
 use plib::PROJECT_NAME;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
 use std::{fs, io};
 
@@ -30,15 +33,15 @@ struct Args {
     force: bool,
 
     /// Follow command line symlinks
-    #[arg(short = 'H', long)]
+    #[arg(short = 'H', long, overrides_with_all = ["dereference", "no_dereference"])]
     follow_cli: bool,
 
     /// Follow symlinks in source
-    #[arg(short = 'L', long, group = "deref")]
+    #[arg(short = 'L', long, overrides_with_all = ["follow_cli", "no_dereference"])]
     dereference: bool,
 
     /// Never follow symlinks in source
-    #[arg(short = 'P', long, group = "deref")]
+    #[arg(short = 'P', long, overrides_with_all = ["follow_cli", "dereference"])]
     no_dereference: bool,
 
     /// Prompt for confirmation if the destination path exists.
@@ -60,6 +63,10 @@ struct Args {
 struct Config {
     force: bool,
     interactive: bool,
+    preserve: bool,
+    recursive: bool,
+    follow_cli: bool,
+    follow_all: bool,
     is_terminal: bool,
 }
 
@@ -68,22 +75,178 @@ impl Config {
         Config {
             force: args.force,
             interactive: args.interactive,
+            preserve: args.preserve,
+            recursive: args.recursive,
+            follow_cli: args.follow_cli,
+            follow_all: args.dereference,
             is_terminal: atty::is(atty::Stream::Stdin),
         }
     }
 }
 
-fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
-    fs::create_dir_all(&dst)?;
+/// Copy a file of any type found during a recursive copy.  `src_md`
+/// was obtained with or without following symlinks, according to the
+/// -H/-L/-P policy.  `ancestors` holds the device and inode of each
+/// directory being copied above this one, to detect symlink cycles.
+///
+/// Errors inside a directory are reported as they occur, and the copy
+/// carries on with the next entry.  Returns Ok(false) if any entry
+/// could not be copied.
+fn copy_tree(
+    cfg: &Config,
+    src: &Path,
+    dst: &Path,
+    src_md: &fs::Metadata,
+    ancestors: &mut Vec<(u64, u64)>,
+) -> io::Result<bool> {
+    let ft = src_md.file_type();
+
+    if ft.is_dir() {
+        let id = (src_md.dev(), src_md.ino());
+        if ancestors.contains(&id) {
+            return Err(io::Error::other(gettext("directory cycle detected")));
+        }
+
+        ancestors.push(id);
+        let res = copy_dir_all(cfg, src, dst, src_md, ancestors);
+        ancestors.pop();
+        return res;
+    }
+
+    // A non-directory already at the destination is replaced by a new
+    // symlink or special file; a regular file is written into in place.
+    if !ft.is_file() && fs::symlink_metadata(dst).is_ok_and(|md| !md.is_dir()) {
+        fs::remove_file(dst)?;
+    }
+
+    if ft.is_file() {
+        copy_regular(cfg, src, dst, src_md)?;
+        Ok(true)
+    } else if ft.is_symlink() {
+        let link_target = fs::read_link(src)?;
+        std::os::unix::fs::symlink(link_target, dst)?;
+        if cfg.preserve {
+            copy_characteristics(src_md, dst)?;
+        }
+        Ok(true)
+    } else {
+        copy_special(cfg, dst, src_md)?;
+        Ok(true)
+    }
+}
+
+/// The file mode creation mask of the process.
+fn umask() -> u32 {
+    unsafe {
+        let mask = libc::umask(0);
+        libc::umask(mask);
+        mask as u32
+    }
+}
+
+fn copy_dir_all(
+    cfg: &Config,
+    src: &Path,
+    dst: &Path,
+    src_md: &fs::Metadata,
+    ancestors: &mut Vec<(u64, u64)>,
+) -> io::Result<bool> {
+    let mut complete = true;
+
+    // A new directory starts out with the source's permission bits, plus
+    // owner access so that its entries can be written; a missing parent
+    // is an error, as it would be for a file.
+    let created = match fs::DirBuilder::new()
+        .mode(src_md.mode() & 0o777 | 0o700)
+        .create(dst)
+    {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists && dst.is_dir() => false,
+        Err(e) => return Err(e),
+    };
+
     for entry in fs::read_dir(src)? {
         let entry = entry?;
-        let ty = entry.file_type()?;
-        if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
+        let entry_path = entry.path();
+
+        // only -L follows symlinks found inside the hierarchy
+        let entry_md = if cfg.follow_all {
+            fs::metadata(&entry_path)
         } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            fs::symlink_metadata(&entry_path)
+        };
+
+        let res = entry_md.and_then(|md| {
+            copy_tree(
+                cfg,
+                &entry_path,
+                &dst.join(entry.file_name()),
+                &md,
+                ancestors,
+            )
+        });
+        match res {
+            Ok(entry_complete) => complete &= entry_complete,
+            Err(e) => {
+                eprintln!("cp: {}: {}", entry_path.display(), e);
+                complete = false;
+            }
         }
     }
+
+    // Directory times are set only once all of its entries are written,
+    // since creating them updates the directory's modification time.
+    if cfg.preserve {
+        copy_characteristics(src_md, dst)?;
+    } else if created && src_md.mode() & 0o700 != 0o700 {
+        let mode = src_md.mode() & 0o777 & !umask();
+        fs::set_permissions(dst, fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(complete)
+}
+
+/// Open the destination of a regular file's data.  An existing file is
+/// truncated and written in place, keeping its inode, links, owner and
+/// mode; only if it cannot be opened, and -f is given, is it removed and
+/// created anew.  A new file gets the source's permission bits, less the
+/// umask.
+fn open_target(cfg: &Config, dst: &Path, src_md: &fs::Metadata) -> io::Result<fs::File> {
+    match fs::OpenOptions::new().write(true).truncate(true).open(dst) {
+        Ok(file) => return Ok(file),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) if cfg.force => fs::remove_file(dst).map_err(|_| e)?,
+        Err(e) => return Err(e),
+    }
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(src_md.mode() & 0o777)
+        .open(dst)
+}
+
+/// Copy a regular file's data.
+fn copy_regular(cfg: &Config, src: &Path, dst: &Path, src_md: &fs::Metadata) -> io::Result<()> {
+    let src_file = fs::File::open(src)?;
+    let dst_file = open_target(cfg, dst, src_md)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", dst.display(), e)))?;
+    plib::copy::copy_fd(src_file.as_raw_fd(), dst_file.as_raw_fd())?;
+    if cfg.preserve {
+        copy_characteristics(src_md, dst)?;
+    }
+
+    Ok(())
+}
+
+/// Recreate a FIFO, device node or socket, rather than copying the data
+/// read from it.
+fn copy_special(cfg: &Config, dst: &Path, src_md: &fs::Metadata) -> io::Result<()> {
+    make_special(src_md, dst)?;
+    if cfg.preserve {
+        copy_characteristics(src_md, dst)?;
+    }
+
     Ok(())
 }
 
@@ -94,88 +257,108 @@ fn prompt_user(prompt: &str) -> bool {
     response.to_lowercase().starts_with('y')
 }
 
-fn copy_file(cfg: &Config, source: &str, target: &str) -> io::Result<()> {
-    // 1. If the destination path exists, conditionally prompt user
-    let target_md = match fs::metadata(target) {
-        Ok(md) => Some(md),
-        Err(e) => {
-            if e.kind() == io::ErrorKind::NotFound {
-                None
-            } else {
-                eprintln!("{}: {}", target, e);
-                return Err(e.into());
+/// Does `target` name `source` itself, or a path inside it?  `target`
+/// need not exist yet, but its parent directory must.
+fn is_within(source: &Path, target: &Path) -> bool {
+    let source = match fs::canonicalize(source) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    let target = match fs::canonicalize(target) {
+        Ok(path) => path,
+        Err(_) => {
+            let parent = match target.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            match (fs::canonicalize(parent), target.file_name()) {
+                (Ok(parent), Some(name)) => parent.join(name),
+                _ => return false,
             }
         }
     };
-    let target_exists = target_md.is_some();
-    let target_is_dir = match target_md {
-        Some(md) => md.is_dir(),
-        None => false,
-    };
-    if target_exists && !cfg.force && (cfg.is_terminal || cfg.interactive) {
-        let is_affirm = prompt_user(&format!("{}: {}", target, gettext("overwrite?")));
-        if !is_affirm {
-            return Ok(());
-        }
-    }
 
-    // 2. source and target are same dirent:  we assume rename handles this case
-    // FIXME?
+    target.starts_with(source)
+}
 
-    // 4. handle source/target dir mismatch
-    let source_md = match fs::metadata(source) {
-        Ok(md) => Some(md),
-        Err(e) => {
-            if e.kind() == io::ErrorKind::NotFound {
-                None
-            } else {
-                eprintln!("{}: {}", source, e);
-                return Err(e.into());
-            }
-        }
-    };
-    let source_is_dir = match source_md {
-        Some(md) => md.is_dir(),
-        None => false,
+/// Copy one source operand to its destination path.  Returns Ok(false)
+/// if part of a hierarchy could not be copied; those errors have already
+/// been reported.
+fn copy_file(cfg: &Config, source: &str, target: &str) -> io::Result<bool> {
+    // 1. Stat the source.  Symlinks named on the command line are
+    // followed, unless copying recursively under -P (the default for -R).
+    let follow_source = !cfg.recursive || cfg.follow_cli || cfg.follow_all;
+    let source_md = if follow_source {
+        fs::metadata(source)?
+    } else {
+        fs::symlink_metadata(source)?
     };
+    let source_is_dir = source_md.is_dir();
 
-    if (target_exists && target_is_dir && !source_is_dir)
-        || (target_exists && !target_is_dir && source_is_dir)
-    {
-        eprintln!(
-            "{}: {}",
-            target,
-            gettext("cannot overwrite directory with non-directory")
-        );
-        return Ok(());
+    if source_is_dir && !cfg.recursive {
+        return Err(io::Error::other(gettext(
+            "is a directory (not copied, -R not specified)",
+        )));
     }
 
-    // 5. remove destination path
-    if target_exists {
-        if target_is_dir {
-            fs::remove_dir_all(target)?;
-        } else {
-            fs::remove_file(target)?;
+    let target_md = match fs::metadata(target) {
+        Ok(md) => Some(md),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", target, e))),
+    };
+    let target_exists = target_md.is_some();
+    let target_is_dir = target_md.as_ref().is_some_and(|md| md.is_dir());
+
+    // 2. refuse to copy a file onto itself
+    if let Some(target_md) = &target_md {
+        let same = |md: &fs::Metadata| md.dev() == target_md.dev() && md.ino() == target_md.ino();
+        if same(&source_md) || fs::metadata(source).is_ok_and(|md| same(&md)) {
+            return Err(io::Error::other(gettext(
+                "source and target are the same file",
+            )));
         }
     }
 
-    // 6. copy source file hierarchy to target
-    if source_is_dir {
-        copy_dir_all(source, target)?;
-    } else {
-        fs::copy(source, target)?;
+    // 3. refuse to copy a directory into its own hierarchy
+    if source_is_dir && is_within(Path::new(source), Path::new(target)) {
+        return Err(io::Error::other(gettext(
+            "cannot copy a directory into itself",
+        )));
     }
 
-    Ok(())
-}
+    // 4. If the destination path exists, conditionally prompt user
+    if target_exists && !cfg.force && (cfg.is_terminal || cfg.interactive) {
+        let is_affirm = prompt_user(&format!("{}: {}", target, gettext("overwrite?")));
+        if !is_affirm {
+            return Ok(true);
+        }
+    }
 
-fn copy_files(cfg: &Config, sources: &[String], target: &str) -> io::Result<()> {
-    // loop through sources, moving each to target
-    for source in sources {
-        copy_file(cfg, source, target)?;
+    // 5. handle source/target dir mismatch
+    if target_exists && target_is_dir != source_is_dir {
+        let msg = if target_is_dir {
+            gettext("cannot overwrite directory with non-directory")
+        } else {
+            gettext("cannot overwrite non-directory with directory")
+        };
+        return Err(io::Error::other(format!("{}: {}", target, msg)));
     }
 
-    Ok(())
+    // 6. copy source file hierarchy to target; an existing directory is
+    // copied into, merging the two hierarchies
+    if cfg.recursive {
+        let mut ancestors = Vec::new();
+        copy_tree(
+            cfg,
+            Path::new(source),
+            Path::new(target),
+            &source_md,
+            &mut ancestors,
+        )
+    } else {
+        copy_regular(cfg, Path::new(source), Path::new(target), &source_md)?;
+        Ok(true)
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -203,19 +386,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 if e.kind() == io::ErrorKind::NotFound {
                     false
                 } else {
-                    eprintln!("{}: {}", target, e);
+                    eprintln!("cp: {}: {}", target, e);
                     std::process::exit(1);
                 }
             }
         }
     };
 
+    if sources.len() > 1 && !dir_exists {
+        eprintln!("cp: {}: {}", target, gettext("Not a directory"));
+        std::process::exit(1);
+    }
+
     let cfg = Config::new(&args);
-    if dir_exists {
-        let _ = copy_files(&cfg, sources, target);
-    } else {
-        let _ = copy_file(&cfg, &sources[0], target);
+    let mut exit_code = 0;
+
+    // copy each source, carrying on past any that fail
+    for source in sources {
+        let dest = if dir_exists {
+            let name = Path::new(source).file_name().unwrap_or_default();
+            Path::new(target).join(name).to_string_lossy().into_owned()
+        } else {
+            target.clone()
+        };
+
+        match copy_file(&cfg, source, &dest) {
+            Ok(true) => {}
+            Ok(false) => exit_code = 1,
+            Err(e) => {
+                eprintln!("cp: {}: {}", source, e);
+                exit_code = 1;
+            }
+        }
     }
 
-    Ok(())
+    std::process::exit(exit_code)
 }
@@ -7,15 +7,29 @@
 // SPDX-License-Identifier: MIT
 //
 
+extern crate libc;
 extern crate plib;
 
 use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
 use plib::PROJECT_NAME;
 use std::fs;
-use std::io::{self, Read, Write};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 const DEF_BLOCK_SIZE: usize = 512;
 
+// buffer alignment for O_DIRECT
+const DIRECT_ALIGN: usize = 4096;
+
+#[cfg(target_os = "linux")]
+const O_DIRECT: libc::c_int = libc::O_DIRECT;
+#[cfg(not(target_os = "linux"))]
+const O_DIRECT: libc::c_int = 0;
+
 const CONV_ASCII_IBM: [u8; 256] = [
     0x0, 0x1, 0x2, 0x3, 0x37, 0x2d, 0x2e, 0x2f, 0x16, 0x5, 0x25, 0xb, 0xc, 0xd, 0xe, 0xf, 0x10,
     0x11, 0x12, 0x13, 0x3c, 0x3d, 0x32, 0x26, 0x18, 0x19, 0x3f, 0x27, 0x1c, 0x1d, 0x1e, 0x1f, 0x40,
@@ -80,11 +94,54 @@ enum AsciiConv {
     IBM,
 }
 
+/// Flags given with iflag= or oflag=.
+#[derive(Debug, Default)]
+struct FileFlags {
+    append: bool,
+    direct: bool,
+    dsync: bool,
+    sync: bool,
+    fullblock: bool,
+    nofollow: bool,
+}
+
+impl FileFlags {
+    /// The open(2) flags these stand for.
+    fn open_flags(&self) -> libc::c_int {
+        let mut flags = 0;
+        if self.append {
+            flags |= libc::O_APPEND;
+        }
+        if self.direct {
+            flags |= O_DIRECT;
+        }
+        if self.dsync {
+            flags |= libc::O_DSYNC;
+        }
+        if self.sync {
+            flags |= libc::O_SYNC;
+        }
+        if self.nofollow {
+            flags |= libc::O_NOFOLLOW;
+        }
+        flags
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum Status {
+    #[default]
+    Default,
+    Quiet,
+    NoXfer,
+    Progress,
+}
+
 #[derive(Debug)]
 struct Config {
     ifile: String,
     ofile: String,
-    bs: usize,
+    bs: Option<usize>,
     ibs: usize,
     obs: usize,
     cbs: usize,
@@ -100,6 +157,13 @@ struct Config {
     noerror: bool,
     notrunc: bool,
     sync: bool,
+    fsync: bool,
+    fdatasync: bool,
+    sparse: bool,
+
+    iflags: FileFlags,
+    oflags: FileFlags,
+    status: Status,
 }
 
 impl Config {
@@ -107,7 +171,7 @@ impl Config {
         Config {
             ifile: String::from("-"),
             ofile: String::from("-"),
-            bs: DEF_BLOCK_SIZE,
+            bs: None,
             ibs: DEF_BLOCK_SIZE,
             obs: DEF_BLOCK_SIZE,
             cbs: 0,
@@ -122,6 +186,219 @@ impl Config {
             noerror: false,
             notrunc: false,
             sync: false,
+            fsync: false,
+            fdatasync: false,
+            sparse: false,
+            iflags: FileFlags::default(),
+            oflags: FileFlags::default(),
+            status: Status::Default,
+        }
+    }
+}
+
+/// State carried from one input block to the next by the block and
+/// unblock conversions, whose records need not line up with blocks.
+#[derive(Default)]
+struct ConvState {
+    // block: bytes of the current output record so far
+    col: usize,
+
+    // block: the current input line is longer than cbs, and its
+    // remainder is being discarded
+    discarding: bool,
+
+    // unblock: the current input record
+    record: Vec<u8>,
+
+    // block: number of input lines truncated to cbs
+    truncated: usize,
+}
+
+/// Counts of full and partial blocks, as reported on exit.
+struct Stats {
+    in_full: usize,
+    in_partial: usize,
+    out_full: usize,
+    out_partial: usize,
+    truncated: usize,
+    bytes_out: u64,
+
+    status: Status,
+    start: Instant,
+    last_progress: Instant,
+    progress_shown: bool,
+}
+
+impl Stats {
+    fn new(status: Status) -> Stats {
+        let now = Instant::now();
+        Stats {
+            in_full: 0,
+            in_partial: 0,
+            out_full: 0,
+            out_partial: 0,
+            truncated: 0,
+            bytes_out: 0,
+            status,
+            start: now,
+            last_progress: now,
+            progress_shown: false,
+        }
+    }
+
+    fn transfer(&self) -> String {
+        let secs = self.start.elapsed().as_secs_f64();
+        let rate = if secs > 0.0 {
+            self.bytes_out as f64 / secs
+        } else {
+            0.0
+        };
+        format!(
+            "{} {}, {:.6} s, {}/s",
+            self.bytes_out,
+            gettext("bytes copied"),
+            secs,
+            human_size(rate)
+        )
+    }
+
+    /// With status=progress, rewrite the transfer line once a second.
+    fn progress(&mut self) {
+        if self.status == Status::Progress && self.last_progress.elapsed() >= Duration::from_secs(1)
+        {
+            eprint!("\r{}", self.transfer());
+            self.last_progress = Instant::now();
+            self.progress_shown = true;
+        }
+    }
+
+    fn report(&self) {
+        if self.status == Status::Quiet {
+            return;
+        }
+        if self.progress_shown {
+            eprintln!();
+        }
+
+        eprintln!(
+            "{}+{} {}",
+            self.in_full,
+            self.in_partial,
+            gettext("records in")
+        );
+        eprintln!(
+            "{}+{} {}",
+            self.out_full,
+            self.out_partial,
+            gettext("records out")
+        );
+        if self.truncated > 0 {
+            let noun = if self.truncated == 1 {
+                gettext("truncated record")
+            } else {
+                gettext("truncated records")
+            };
+            eprintln!("{} {}", self.truncated, noun);
+        }
+
+        if self.status != Status::NoXfer {
+            eprintln!("{}", self.transfer());
+        }
+    }
+}
+
+/// Format a byte count in SI units, as in "12.3 MB".
+fn human_size(n: f64) -> String {
+    const UNITS: [&str; 7] = ["B", "kB", "MB", "GB", "TB", "PB", "EB"];
+
+    let mut n = n;
+    let mut unit = 0;
+    while n >= 1000.0 && unit < UNITS.len() - 1 {
+        n /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{:.0} {}", n, UNITS[unit])
+    } else {
+        format!("{:.1} {}", n, UNITS[unit])
+    }
+}
+
+/// A zero-filled buffer that starts on a boundary suitable for O_DIRECT.
+struct AlignedBuf {
+    storage: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    fn new(len: usize) -> AlignedBuf {
+        let storage = vec![0u8; len + DIRECT_ALIGN];
+        let offset = storage.as_ptr().align_offset(DIRECT_ALIGN);
+        AlignedBuf {
+            storage,
+            offset,
+            len,
+        }
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.storage[self.offset..self.offset + self.len]
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.storage[self.offset..self.offset + self.len]
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INFO_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(sig: libc::c_int) {
+    if sig == libc::SIGINT {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    } else {
+        INFO_REQUESTED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Catch SIGINT, to report statistics before dying, and SIGUSR1, to
+/// report them and carry on.  SA_RESTART is left off, so that a read
+/// blocked on a slow device returns and the signal is seen promptly.
+fn install_signal_handlers() {
+    for sig in [libc::SIGINT, libc::SIGUSR1] {
+        unsafe {
+            let mut old: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(sig, std::ptr::null(), &mut old);
+            if old.sa_sigaction == libc::SIG_IGN {
+                // leave signals ignored by the parent ignored
+                continue;
+            }
+
+            let mut act: libc::sigaction = std::mem::zeroed();
+            act.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut act.sa_mask);
+            libc::sigaction(sig, &act, std::ptr::null_mut());
+        }
+    }
+}
+
+fn check_signals(stats: &Stats) {
+    if INFO_REQUESTED.swap(false, Ordering::SeqCst) {
+        stats.report();
+    }
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        stats.report();
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::raise(libc::SIGINT);
         }
     }
 }
@@ -140,6 +417,9 @@ fn parse_conv_list(config: &mut Config, s: &str) -> Result<(), Box<dyn std::erro
             "noerror" => config.noerror = true,
             "notrunc" => config.notrunc = true,
             "sync" => config.sync = true,
+            "fsync" => config.fsync = true,
+            "fdatasync" => config.fdatasync = true,
+            "sparse" => config.sparse = true,
             _ => {
                 eprintln!("{}: {}", gettext("invalid conv option"), convstr);
                 return Err("invalid conv option".into());
@@ -149,6 +429,45 @@ fn parse_conv_list(config: &mut Config, s: &str) -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
+fn parse_flag_list(
+    flags: &mut FileFlags,
+    s: &str,
+    is_output: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for flagstr in s.split(",") {
+        match flagstr {
+            "append" => flags.append = true,
+            "direct" if O_DIRECT != 0 => flags.direct = true,
+            "dsync" => flags.dsync = true,
+            "sync" => flags.sync = true,
+            "fullblock" if !is_output => flags.fullblock = true,
+            "nofollow" => flags.nofollow = true,
+            _ => {
+                let msg = if is_output {
+                    gettext("invalid output flag")
+                } else {
+                    gettext("invalid input flag")
+                };
+                eprintln!("{}: {}", msg, flagstr);
+                return Err(msg.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_status(s: &str) -> Result<Status, Box<dyn std::error::Error>> {
+    match s {
+        "none" => Ok(Status::Quiet),
+        "noxfer" => Ok(Status::NoXfer),
+        "progress" => Ok(Status::Progress),
+        _ => {
+            eprintln!("{}: {}", gettext("invalid status level"), s);
+            Err("invalid status level".into())
+        }
+    }
+}
+
 fn parse_block_size(s: &str) -> Result<usize, Box<dyn std::error::Error>> {
     let mut s = s.to_string();
     let mut scale = 1;
@@ -196,105 +515,520 @@ fn parse_cmdline(args: &[String]) -> Result<Config, Box<dyn std::error::Error>>
             "ibs" => config.ibs = parse_block_size(&oparg)?,
             "obs" => config.obs = parse_block_size(&oparg)?,
             "bs" => {
-                config.bs = parse_block_size(&oparg)?;
-                config.ibs = config.bs;
-                config.obs = config.bs;
+                let bs = parse_block_size(&oparg)?;
+                config.bs = Some(bs);
+                config.ibs = bs;
+                config.obs = bs;
             }
             "cbs" => config.cbs = parse_block_size(&oparg)?,
             "skip" => config.skip = oparg.parse::<usize>()?,
             "seek" => config.seek = oparg.parse::<usize>()?,
             "count" => config.count = oparg.parse::<usize>()?,
             "conv" => parse_conv_list(&mut config, &oparg)?,
+            "iflag" => parse_flag_list(&mut config.iflags, &oparg, false)?,
+            "oflag" => parse_flag_list(&mut config.oflags, &oparg, true)?,
+            "status" => config.status = parse_status(&oparg)?,
 
             _ => {
                 eprintln!("{}: {}", gettext("invalid option"), op);
             }
         }
     }
+
+    if config.block.is_some() && config.cbs == 0 {
+        let msg = gettext("block and unblock require a nonzero cbs");
+        eprintln!("{}", msg);
+        return Err(msg.into());
+    }
+
     Ok(config)
 }
 
-fn copy_convert_file(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let mut ifile: Box<dyn Read>;
+/// Convert newline-terminated lines into records of exactly cbs bytes,
+/// padded with spaces; the excess of longer lines is dropped.
+fn block_records(cbs: usize, state: &mut ConvState, data: &[u8], out: &mut Vec<u8>) {
+    for &ch in data {
+        if ch == b'\n' {
+            out.resize(out.len() + (cbs - state.col), b' ');
+            state.col = 0;
+            state.discarding = false;
+        } else if state.col < cbs {
+            out.push(ch);
+            state.col += 1;
+        } else if !state.discarding {
+            state.truncated += 1;
+            state.discarding = true;
+        }
+    }
+}
+
+/// Convert records of cbs bytes into lines, dropping trailing spaces.
+fn unblock_records(cbs: usize, state: &mut ConvState, data: &[u8], out: &mut Vec<u8>) {
+    for &ch in data {
+        state.record.push(ch);
+        if state.record.len() == cbs {
+            flush_record(state, out);
+        }
+    }
+}
+
+fn flush_record(state: &mut ConvState, out: &mut Vec<u8>) {
+    let len = state
+        .record
+        .iter()
+        .rposition(|&ch| ch != b' ')
+        .map_or(0, |pos| pos + 1);
+    out.extend_from_slice(&state.record[..len]);
+    out.push(b'\n');
+    state.record.clear();
+}
+
+/// Apply the conversions to one input block, appending the result to
+/// `out`.  The character set conversions from EBCDIC are done first,
+/// and those to EBCDIC last, so that case conversion, block and unblock
+/// always see ASCII.
+fn convert_block(config: &Config, state: &mut ConvState, buf: &mut [u8], out: &mut Vec<u8>) {
+    if config.swab {
+        for pair in buf.chunks_exact_mut(2) {
+            pair.swap(0, 1);
+        }
+    }
+
+    if let Some(AsciiConv::Ascii) = config.ascii {
+        for ch in buf.iter_mut() {
+            *ch = CONV_EBCDIC_ASCII[*ch as usize];
+        }
+    }
+
+    if config.lcase {
+        buf.make_ascii_lowercase();
+    } else if config.ucase {
+        buf.make_ascii_uppercase();
+    }
+
+    let start = out.len();
+    match config.block {
+        Some(true) => block_records(config.cbs, state, buf, out),
+        Some(false) => unblock_records(config.cbs, state, buf, out),
+        None => out.extend_from_slice(buf),
+    }
+
+    match config.ascii {
+        Some(AsciiConv::EBCDIC) => translate(&CONV_ASCII_EBCDIC, &mut out[start..]),
+        Some(AsciiConv::IBM) => translate(&CONV_ASCII_IBM, &mut out[start..]),
+        _ => {}
+    }
+}
+
+/// Complete a record left partial at the end of the input.
+fn finish_conversion(config: &Config, state: &mut ConvState, out: &mut Vec<u8>) {
+    let start = out.len();
+    match config.block {
+        Some(true) if state.col > 0 || state.discarding => {
+            block_records(config.cbs, state, b"\n", out)
+        }
+        Some(false) if !state.record.is_empty() => flush_record(state, out),
+        _ => {}
+    }
+
+    match config.ascii {
+        Some(AsciiConv::EBCDIC) => translate(&CONV_ASCII_EBCDIC, &mut out[start..]),
+        Some(AsciiConv::IBM) => translate(&CONV_ASCII_IBM, &mut out[start..]),
+        _ => {}
+    }
+}
+
+fn translate(table: &[u8; 256], buf: &mut [u8]) {
+    for ch in buf.iter_mut() {
+        *ch = table[*ch as usize];
+    }
+}
+
+/// Read one input block, retrying reads interrupted by a signal.  With
+/// iflag=fullblock, keep reading until the block is full or the input
+/// ends.
+fn read_block(
+    ifile: &mut fs::File,
+    buf: &mut [u8],
+    fullblock: bool,
+    stats: &Stats,
+) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match ifile.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(n) => {
+                total += n;
+                if !fullblock {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => check_signals(stats),
+
+            // return what was read; the error will recur on the next read
+            Err(_) if total > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+fn annotate(name: &str, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", name, e))
+}
+
+/// Add open(2) flags to an already open standard input or output.
+fn set_fd_flags(file: &fs::File, flags: libc::c_int) -> io::Result<()> {
+    if flags == 0 {
+        return Ok(());
+    }
+
+    let fd = file.as_raw_fd();
+    let ret = unsafe {
+        let old = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, old | flags)
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn open_input(config: &Config) -> io::Result<fs::File> {
+    let flags = config.iflags.open_flags();
+
+    // Standard input is used unbuffered, like any named file, so that
+    // each read(2) is exactly one input block.
     if config.ifile == "-" {
-        ifile = Box::new(io::stdin().lock());
-    } else {
-        ifile = Box::new(fs::File::open(&config.ifile)?);
+        let file = unsafe { fs::File::from_raw_fd(libc::STDIN_FILENO) };
+        set_fd_flags(&file, flags)?;
+        return Ok(file);
     }
-    let mut ofile: Box<dyn Write>;
+
+    fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(flags)
+        .open(&config.ifile)
+}
+
+fn open_output(config: &Config) -> io::Result<fs::File> {
+    let flags = config.oflags.open_flags();
+
     if config.ofile == "-" {
-        ofile = Box::new(io::stdout().lock())
-    } else {
-        ofile = Box::new(fs::File::create(&config.ofile)?)
+        let file = unsafe { fs::File::from_raw_fd(libc::STDOUT_FILENO) };
+        set_fd_flags(&file, flags)?;
+        return Ok(file);
     }
 
-    let mut ibuf = vec![0u8; config.ibs];
-    let mut obuf = vec![0u8; config.obs];
+    // With seek=, the blocks before the seek point are kept, and the
+    // file is truncated only once positioned.
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(!config.notrunc && !config.oflags.append && config.seek == 0)
+        .custom_flags(flags)
+        .open(&config.ofile)
+}
 
-    let mut count = 0;
-    let mut skip = config.skip;
-    let mut seek = config.seek;
+/// The output file, along with the state needed to write it in blocks.
+struct Output {
+    file: fs::File,
 
-    loop {
-        if skip > 0 {
-            let n = ifile.read(&mut ibuf)?;
-            if n == 0 {
-                break;
+    // with oflag=direct, each block is copied here, for alignment
+    direct_buf: Option<AlignedBuf>,
+
+    // conv=sparse seeked over the last block rather than writing it
+    hole_at_end: bool,
+}
+
+impl Output {
+    fn new(config: &Config, file: fs::File) -> Output {
+        let direct_buf = if config.oflags.direct {
+            Some(AlignedBuf::new(config.obs.max(config.ibs)))
+        } else {
+            None
+        };
+
+        Output {
+            file,
+            direct_buf,
+            hole_at_end: false,
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8], stats: &Stats) -> io::Result<()> {
+        // O_DIRECT cannot write a block of odd size, which can only be
+        // the last one, so it is turned off for that
+        if self.direct_buf.is_some() && !buf.len().is_multiple_of(DIRECT_ALIGN) {
+            let fd = self.file.as_raw_fd();
+            unsafe {
+                let old = libc::fcntl(fd, libc::F_GETFL);
+                libc::fcntl(fd, libc::F_SETFL, old & !O_DIRECT);
             }
-            skip -= n;
-            continue;
+            self.direct_buf = None;
         }
 
-        if seek > 0 {
-            let n = ifile.read(&mut ibuf)?;
-            if n == 0 {
-                break;
+        match &mut self.direct_buf {
+            Some(dbuf) => {
+                dbuf[..buf.len()].copy_from_slice(buf);
+                write_fd(self.file.as_raw_fd(), &dbuf[..buf.len()], stats)
+            }
+            None => write_fd(self.file.as_raw_fd(), buf, stats),
+        }
+    }
+}
+
+/// Write all of `buf` with write(2).  Unlike Write::write_all, which
+/// quietly retries, a write interrupted by a signal lets it be handled.
+fn write_fd(fd: libc::c_int, mut buf: &[u8], stats: &Stats) -> io::Result<()> {
+    while !buf.is_empty() {
+        let ret = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                check_signals(stats);
+                continue;
             }
-            seek -= n;
-            continue;
+            return Err(e);
+        }
+        if ret == 0 {
+            return Err(io::Error::from(io::ErrorKind::WriteZero));
         }
+        buf = &buf[ret as usize..];
+    }
+    Ok(())
+}
 
-        let n = ifile.read(&mut ibuf)?;
+/// Write one output block.  With conv=sparse, a block of NULs is
+/// skipped over where the output allows it.
+fn write_block(config: &Config, out: &mut Output, buf: &[u8], stats: &mut Stats) -> io::Result<()> {
+    if config.sparse
+        && buf.iter().all(|&ch| ch == 0)
+        && out.file.seek(SeekFrom::Current(buf.len() as i64)).is_ok()
+    {
+        out.hole_at_end = true;
+    } else {
+        out.write_all(buf, stats)
+            .map_err(|e| annotate(&config.ofile, e))?;
+        out.hole_at_end = false;
+    }
+
+    stats.bytes_out += buf.len() as u64;
+    if buf.len() == config.obs {
+        stats.out_full += 1;
+    } else {
+        stats.out_partial += 1;
+    }
+    Ok(())
+}
+
+/// Write out as many full output blocks as `obuf` holds, keeping the
+/// remainder for later.
+fn write_full_blocks(
+    config: &Config,
+    out: &mut Output,
+    obuf: &mut Vec<u8>,
+    stats: &mut Stats,
+) -> io::Result<()> {
+    let mut written = 0;
+    while obuf.len() - written >= config.obs {
+        write_block(config, out, &obuf[written..written + config.obs], stats)?;
+        written += config.obs;
+    }
+    obuf.drain(..written);
+    Ok(())
+}
+
+/// Skip the first skip= input blocks: by seeking where the input
+/// allows it, otherwise by reading and discarding them.
+fn skip_input(
+    config: &Config,
+    ifile: &mut fs::File,
+    ibuf: &mut [u8],
+    stats: &Stats,
+) -> io::Result<()> {
+    if config.skip == 0 {
+        return Ok(());
+    }
+
+    let offset = config.skip as u64 * config.ibs as u64;
+    if let Ok(pos) = ifile.seek(SeekFrom::Current(offset as i64)) {
+        // seeking past the end succeeds, so check against the size of
+        // a file or block device
+        let md = ifile.metadata().map_err(|e| annotate(&config.ifile, e))?;
+        let end = if md.is_file() {
+            Some(md.len())
+        } else if md.file_type().is_block_device() {
+            let end = ifile.seek(SeekFrom::End(0))?;
+            ifile.seek(SeekFrom::Start(pos))?;
+            Some(end)
+        } else {
+            None
+        };
+        if end.is_some_and(|end| pos > end) {
+            eprintln!(
+                "dd: {}: {}",
+                config.ifile,
+                gettext("cannot skip past end of input")
+            );
+        }
+        return Ok(());
+    }
+
+    for _ in 0..config.skip {
+        let n = read_block(ifile, ibuf, config.iflags.fullblock, stats)
+            .map_err(|e| annotate(&config.ifile, e))?;
         if n == 0 {
+            eprintln!(
+                "dd: {}: {}",
+                config.ifile,
+                gettext("cannot skip to specified offset")
+            );
             break;
         }
+    }
+    Ok(())
+}
+
+/// Position the output past the first seek= output blocks: by seeking
+/// where the output allows it, otherwise by writing NUL blocks.
+fn seek_output(config: &Config, out: &mut Output, stats: &Stats) -> io::Result<()> {
+    if config.seek == 0 {
+        return Ok(());
+    }
 
-        if config.count > 0 {
-            if count >= config.count {
-                break;
+    let offset = config.seek as u64 * config.obs as u64;
+    match out.file.seek(SeekFrom::Current(offset as i64)) {
+        Ok(pos) => {
+            // the rest of the file is replaced, unless conv=notrunc
+            if !config.notrunc && !config.oflags.append && out.file.metadata()?.is_file() {
+                out.file.set_len(pos)?;
+            }
+        }
+        Err(_) => {
+            let zeros = vec![0u8; config.obs];
+            for _ in 0..config.seek {
+                out.write_all(&zeros, stats)?;
             }
-            count += 1;
         }
+    }
+    Ok(())
+}
 
-        let ibuf = &ibuf[..n];
-        let obuf = &mut obuf[..n];
+/// After a read error under conv=noerror, step over the unreadable
+/// block.  Returns false if there is no more input to try.
+fn skip_bad_block(ifile: &mut fs::File, ibs: usize) -> bool {
+    let pos = match ifile.seek(SeekFrom::Current(ibs as i64)) {
+        Ok(pos) => pos,
+        Err(_) => return false,
+    };
+    match ifile.seek(SeekFrom::End(0)) {
+        Ok(end) => pos < end && ifile.seek(SeekFrom::Start(pos)).is_ok(),
 
-        if let Some(ascii) = &config.ascii {
-            match ascii {
-                AsciiConv::Ascii => {
-                    // convert EBCDIC to ASCII
-                    for i in 0..n {
-                        obuf[i] = CONV_EBCDIC_ASCII[ibuf[i] as usize];
-                    }
-                }
-                AsciiConv::EBCDIC => {
-                    // convert ASCII to EBCDIC
-                    for i in 0..n {
-                        obuf[i] = CONV_ASCII_EBCDIC[ibuf[i] as usize];
-                    }
+        // no known end, as with some devices
+        Err(_) => true,
+    }
+}
+
+fn copy_convert_file(config: &Config, stats: &mut Stats) -> io::Result<()> {
+    let mut ifile = open_input(config).map_err(|e| annotate(&config.ifile, e))?;
+    let ofile = open_output(config).map_err(|e| annotate(&config.ofile, e))?;
+    let mut out = Output::new(config, ofile);
+
+    // With bs= and no conversions, each input block is written out
+    // as it was read, short or not; otherwise output is reblocked
+    // into obs-sized blocks.
+    let reblock = config.bs.is_none()
+        || config.ascii.is_some()
+        || config.block.is_some()
+        || config.lcase
+        || config.ucase
+        || config.swab;
+
+    let mut ibuf = AlignedBuf::new(config.ibs);
+    let mut obuf = Vec::with_capacity(config.ibs.max(config.obs) * 2);
+    let mut state = ConvState::default();
+
+    skip_input(config, &mut ifile, &mut ibuf, stats)?;
+    seek_output(config, &mut out, stats).map_err(|e| annotate(&config.ofile, e))?;
+
+    let mut count = 0;
+    loop {
+        check_signals(stats);
+        stats.progress();
+
+        if config.count > 0 && count >= config.count {
+            break;
+        }
+
+        let mut n = match read_block(&mut ifile, &mut ibuf, config.iflags.fullblock, stats) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if config.noerror => {
+                eprintln!("dd: {}: {}", config.ifile, e);
+                if !skip_bad_block(&mut ifile, config.ibs) {
+                    break;
                 }
-                AsciiConv::IBM => {
-                    // convert ASCII to IBM
-                    for i in 0..n {
-                        obuf[i] = CONV_ASCII_IBM[ibuf[i] as usize];
-                    }
+
+                // the unreadable block is dropped, or with sync,
+                // replaced by a block of padding
+                stats.in_partial += 1;
+                if !config.sync {
+                    count += 1;
+                    continue;
                 }
+                0
             }
+            Err(e) => return Err(annotate(&config.ifile, e)),
+        };
+        count += 1;
+        if n == config.ibs {
+            stats.in_full += 1;
+        } else if n > 0 {
+            stats.in_partial += 1;
+        }
+
+        if config.sync && n < config.ibs {
+            let pad = if config.block.is_some() { b' ' } else { 0 };
+            ibuf[n..].fill(pad);
+            n = config.ibs;
+        }
+
+        convert_block(config, &mut state, &mut ibuf[..n], &mut obuf);
+        stats.truncated = state.truncated;
+
+        if reblock {
+            write_full_blocks(config, &mut out, &mut obuf, stats)?;
         } else {
-            obuf.copy_from_slice(ibuf);
+            write_block(config, &mut out, &obuf, stats)?;
+            obuf.clear();
         }
+    }
 
-        ofile.write(&obuf)?;
+    // flush what is left, as a final partial block
+    finish_conversion(config, &mut state, &mut obuf);
+    write_full_blocks(config, &mut out, &mut obuf, stats)?;
+    if !obuf.is_empty() {
+        write_block(config, &mut out, &obuf, stats)?;
+    }
+
+    // a hole at the end of the output exists only once the file is
+    // extended over it
+    if out.hole_at_end {
+        let pos = out.file.stream_position()?;
+        out.file
+            .set_len(pos)
+            .map_err(|e| annotate(&config.ofile, e))?;
+    }
+
+    if config.fsync {
+        out.file
+            .sync_all()
+            .map_err(|e| annotate(&config.ofile, e))?;
+    } else if config.fdatasync {
+        out.file
+            .sync_data()
+            .map_err(|e| annotate(&config.ofile, e))?;
     }
 
     Ok(())
@@ -305,9 +1039,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
     let args: Vec<String> = std::env::args().skip(1).collect();
-    let config = parse_cmdline(&args)?;
+    let mut config = parse_cmdline(&args)?;
 
-    copy_convert_file(&config)?;
+    install_signal_handlers();
 
-    Ok(())
+    let mut stats = Stats::new(std::mem::take(&mut config.status));
+    let res = copy_convert_file(&config, &mut stats);
+    if let Err(e) = &res {
+        eprintln!("dd: {}", e);
+    }
+    stats.report();
+
+    std::process::exit(if res.is_ok() { 0 } else { 1 })
 }
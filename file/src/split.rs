@@ -11,11 +11,12 @@ extern crate clap;
 extern crate plib;
 
 use clap::Parser;
-use gettextrs::{bind_textdomain_codeset, textdomain};
+use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
 use plib::PROJECT_NAME;
 use std::cmp;
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, BufRead, Error, ErrorKind, Read, Write};
+use std::io::{self, BufRead, Read, Write};
+use std::process::{Child, Command, Stdio};
 
 /// split - split a file into pieces
 #[derive(Parser, Debug)]
@@ -25,14 +26,41 @@ struct Args {
     #[arg(short='a', long, default_value_t=2, value_parser = clap::value_parser!(u32).range(1..))]
     suffix_len: u32,
 
-    /// Use suffix_length letters to form the suffix portion of the filenames of the split file.
+    /// Use numeric suffixes, starting at FROM (default 0), instead of alphabetic ones.
+    #[arg(
+        short = 'd',
+        long = "numeric-suffixes",
+        value_name = "FROM",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0"
+    )]
+    numeric_suffixes: Option<u64>,
+
+    /// Append an additional suffix to the filenames of the split file.
+    #[arg(long, value_name = "SUFFIX", default_value = "")]
+    additional_suffix: String,
+
+    /// Split a file into pieces n lines in size.
     #[arg(short, long, group = "mode", value_parser = clap::value_parser!(u64).range(1..))]
     lines: Option<u64>,
 
-    /// Split a file into pieces n bytes in size.
+    /// Split a file into pieces n bytes in size; n may be followed by k (kilobytes) or m (megabytes).
     #[arg(short, long, group = "mode")]
     bytes: Option<String>,
 
+    /// Put at most n bytes of whole lines in each piece; n is as for -b.
+    #[arg(short = 'C', long, group = "mode")]
+    line_bytes: Option<String>,
+
+    /// Split a file into N pieces of equal size, l/N pieces without splitting lines, or r/N pieces by distributing lines round-robin.
+    #[arg(short = 'n', long, group = "mode", value_name = "CHUNKS")]
+    number: Option<String>,
+
+    /// Write each piece to the standard input of the shell command CMD, with FILE set to its filename.
+    #[arg(long, value_name = "CMD")]
+    filter: Option<String>,
+
     /// File to be split
     #[arg(default_value = "")]
     file: String,
@@ -42,60 +70,183 @@ struct Args {
     prefix: String,
 }
 
-fn inc_char(ch: char) -> char {
-    ((ch as u8) + 1) as char
+/// Generates the filenames of the split file: prefix, suffix, then any
+/// additional suffix.
+struct Names {
+    prefix: String,
+    additional: String,
+    suffix: Vec<u8>,
+    first: u8,
+    last: u8,
+    started: bool,
 }
 
-struct OutputState {
-    prefix: String,
-    boundary: u64,
+impl Names {
+    fn new(args: &Args) -> io::Result<Names> {
+        let len = args.suffix_len as usize;
+        let (first, last, suffix) = match args.numeric_suffixes {
+            Some(from) => {
+                let suffix = format!("{:0len$}", from);
+                if suffix.len() > len {
+                    return Err(io::Error::other(gettext(
+                        "numeric suffix start value too large for the suffix length",
+                    )));
+                }
+                (b'0', b'9', suffix.into_bytes())
+            }
+            None => (b'a', b'z', vec![b'a'; len]),
+        };
+
+        Ok(Names {
+            prefix: args.prefix.clone(),
+            additional: args.additional_suffix.clone(),
+            suffix,
+            first,
+            last,
+            started: false,
+        })
+    }
 
-    suffix: String,
-    suffix_len: u32,
-    count: u64,
-    outf: Option<File>,
-}
+    /// The number of filenames that remain to be generated.
+    fn remaining(&self) -> u64 {
+        let radix = (self.last - self.first + 1) as u64;
+        let mut used: u64 = 0;
+        let mut total: u64 = 1;
+        for &ch in &self.suffix {
+            used = used
+                .saturating_mul(radix)
+                .saturating_add((ch - self.first) as u64);
+            total = total.saturating_mul(radix);
+        }
+        if self.started {
+            used += 1;
+        }
+        total - used
+    }
 
-impl OutputState {
-    fn new(prefix: &str, boundary: u64, suffix_len: u32) -> OutputState {
-        OutputState {
-            prefix: String::from(prefix),
-            boundary,
-            suffix_len,
-            suffix: String::new(),
-            count: 0,
-            outf: None,
+    fn next(&mut self) -> io::Result<String> {
+        if self.started && !self.incr_suffix() {
+            return Err(io::Error::other(gettext("output file suffixes exhausted")));
         }
+        self.started = true;
+
+        // the suffix is ASCII letters or digits
+        Ok(format!(
+            "{}{}{}",
+            self.prefix,
+            String::from_utf8_lossy(&self.suffix),
+            self.additional
+        ))
     }
 
-    fn incr_suffix(&mut self) -> Result<(), &'static str> {
-        assert!(self.suffix_len > 1);
+    fn incr_suffix(&mut self) -> bool {
+        for ch in self.suffix.iter_mut().rev() {
+            if *ch != self.last {
+                *ch += 1;
+                return true;
+            }
+            *ch = self.first;
+        }
 
-        if self.suffix.is_empty() {
-            self.suffix = String::from("a".repeat(self.suffix_len as usize));
-            return Ok(());
+        false
+    }
+}
+
+/// Destination of one piece of the split file.
+trait OutputSink: Write {
+    /// Complete the piece, reporting any failure to produce it.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl OutputSink for File {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A piece written to the standard input of a --filter command.
+struct FilterSink {
+    name: String,
+    command: String,
+    child: Child,
+}
+
+impl Write for FilterSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let stdin = self.child.stdin.as_mut().unwrap();
+        match stdin.write(buf) {
+            // the command need not read all of its input
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(buf.len()),
+            res => res,
         }
+    }
 
-        assert!(self.suffix.len() > 1);
-        let mut i = self.suffix.len() - 1;
-        loop {
-            let ch = self.suffix.chars().nth(i).unwrap();
-            if ch != 'z' {
-                self.suffix
-                    .replace_range(i..i + 1, inc_char(ch).to_string().as_str());
-                return Ok(());
-            }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
 
-            self.suffix
-                .replace_range(i..i + 1, 'a'.to_string().as_str());
+impl OutputSink for FilterSink {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        drop(self.child.stdin.take());
 
-            if i == 0 {
-                break;
-            }
-            i = i - 1;
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "FILE={}: {}: {}",
+                self.name, self.command, status
+            )));
         }
+        Ok(())
+    }
+}
 
-        Err("maximum suffix reached")
+fn open_sink(name: &str, filter: Option<&str>) -> io::Result<Box<dyn OutputSink>> {
+    match filter {
+        None => {
+            let f = OpenOptions::new()
+                .read(false)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(name)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+            Ok(Box::new(f))
+        }
+        Some(command) => {
+            let child = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("FILE", name)
+                .stdin(Stdio::piped())
+                .spawn()?;
+            Ok(Box::new(FilterSink {
+                name: String::from(name),
+                command: String::from(command),
+                child,
+            }))
+        }
+    }
+}
+
+struct OutputState {
+    names: Names,
+    filter: Option<String>,
+    boundary: u64,
+
+    count: u64,
+    outf: Option<Box<dyn OutputSink>>,
+}
+
+impl OutputState {
+    fn new(args: &Args, boundary: u64) -> io::Result<OutputState> {
+        Ok(OutputState {
+            names: Names::new(args)?,
+            filter: args.filter.clone(),
+            boundary,
+            count: 0,
+            outf: None,
+        })
     }
 
     fn open_output(&mut self) -> io::Result<()> {
@@ -103,46 +254,36 @@ impl OutputState {
             return Ok(());
         }
 
-        let inc_res = self.incr_suffix();
-        if let Err(e) = inc_res {
-            return Err(Error::new(ErrorKind::Other, e));
-        }
-
-        let out_fn = format!("{}{}", self.prefix, self.suffix);
-        let f = OpenOptions::new()
-            .read(false)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&out_fn)?;
-        self.outf = Some(f);
+        // Running out of suffixes is an error only once more data
+        // remains; the files already written are kept, as POSIX requires.
+        let name = self.names.next()?;
+        self.outf = Some(open_sink(&name, self.filter.as_deref())?);
 
         Ok(())
     }
 
-    fn close_output(&mut self) {
-        if self.outf.is_some() {
-            self.outf = None;
-            self.count = 0;
+    fn close_output(&mut self) -> io::Result<()> {
+        self.count = 0;
+        match self.outf.take() {
+            Some(f) => f.finish(),
+            None => Ok(()),
         }
     }
 
-    fn incr_output(&mut self, n: u64) {
-        self.count = self.count + n;
+    fn incr_output(&mut self, n: u64) -> io::Result<()> {
+        self.count += n;
         assert!(self.count <= self.boundary);
 
         if self.count == self.boundary {
-            self.close_output();
+            self.close_output()?;
         }
+        Ok(())
     }
 
     fn write(&mut self, buf: &[u8]) -> io::Result<()> {
         match &mut self.outf {
-            None => {
-                assert!(false);
-                Ok(())
-            }
-            Some(ref mut f) => f.write_all(buf),
+            None => unreachable!(),
+            Some(f) => f.write_all(buf),
         }
     }
 
@@ -153,53 +294,77 @@ impl OutputState {
 
             let remainder = buf.len() - consumed;
             let dist = self.boundary - self.count;
-            let wlen = cmp::min(dist as usize, remainder);
+            let wlen = cmp::min(dist, remainder as u64) as usize;
             let slice = &buf[consumed..consumed + wlen];
             self.write(slice)?;
 
-            consumed = consumed + wlen;
+            consumed += wlen;
 
-            self.incr_output(wlen as u64);
+            self.incr_output(wlen as u64)?;
         }
 
         Ok(())
     }
 }
 
-fn split_by_bytes(args: &Args, bytesplit: String) -> io::Result<()> {
-    let mul: u64 = {
-        if bytesplit.ends_with("k") {
-            1024
-        } else if bytesplit.ends_with("m") {
-            1024 * 1024
-        } else if bytesplit.ends_with("g") {
-            1024 * 1024 * 1024
-        } else {
-            1
-        }
-    };
-    let bytestr = match mul {
-        1 => &bytesplit[..],
-        _ => &bytesplit[0..bytesplit.len() - 1],
-    };
-    let boundary: u64 = match bytestr.parse::<u64>() {
-        Ok(n) => n * mul,
-        Err(e) => {
-            eprintln!("{}", e);
-            return Err(Error::new(ErrorKind::Other, "invalid byte spec"));
+/// Open the input file, or stdin if it is absent or "-".
+fn open_input(filename: &str) -> io::Result<Box<dyn Read>> {
+    if filename.is_empty() || filename == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+
+    match fs::File::open(filename) {
+        Ok(f) => Ok(Box::new(f)),
+        Err(e) => Err(io::Error::new(e.kind(), format!("{}: {}", filename, e))),
+    }
+}
+
+/// Open the input and find its size, which splitting into equal pieces
+/// needs in advance.  Input other than a regular file is read into
+/// memory.
+fn open_sized_input(filename: &str) -> io::Result<(Box<dyn Read>, u64)> {
+    let mut file = open_input(filename)?;
+
+    if !filename.is_empty() && filename != "-" {
+        let metadata = fs::metadata(filename)?;
+        if metadata.is_file() {
+            return Ok((file, metadata.len()));
         }
+    }
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    let len = data.len() as u64;
+    Ok((Box::new(io::Cursor::new(data)), len))
+}
+
+/// Parse a byte count for -b: a decimal number, optionally followed by
+/// k (1024) or m (1048576), and as an extension, g (1073741824).
+fn parse_byte_count(s: &str) -> io::Result<u64> {
+    let (digits, mul) = match s.chars().last() {
+        Some('k') | Some('K') => (&s[..s.len() - 1], 1024),
+        Some('m') | Some('M') => (&s[..s.len() - 1], 1024 * 1024),
+        Some('g') | Some('G') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
     };
 
-    // open file, or stdin
-    let mut file: Box<dyn Read>;
-    if args.file == "" {
-        file = Box::new(io::stdin().lock());
-    } else {
-        file = Box::new(fs::File::open(&args.file)?);
+    match digits.parse::<u64>().ok().and_then(|n| n.checked_mul(mul)) {
+        Some(n) if n > 0 => Ok(n),
+        _ => Err(io::Error::other(format!(
+            "{}: {}",
+            gettext("invalid number of bytes"),
+            s
+        ))),
     }
+}
+
+fn split_by_bytes(args: &Args, bytesplit: &str) -> io::Result<()> {
+    let boundary = parse_byte_count(bytesplit)?;
+
+    let mut file = open_input(&args.file)?;
 
     let mut raw_buffer = [0; plib::BUFSZ];
-    let mut state = OutputState::new(&args.prefix, boundary, args.suffix_len);
+    let mut state = OutputState::new(args, boundary)?;
 
     loop {
         // read a chunk of file data
@@ -214,40 +379,196 @@ fn split_by_bytes(args: &Args, bytesplit: String) -> io::Result<()> {
         state.output_bytes(buf)?;
     }
 
-    Ok(())
+    state.close_output()
 }
 
 fn split_by_lines(args: &Args, linesplit: u64) -> io::Result<()> {
     assert!(linesplit > 0);
 
-    // open file, or stdin
-    let file: Box<dyn Read>;
-    if args.file == "" {
-        file = Box::new(io::stdin().lock());
-    } else {
-        file = Box::new(fs::File::open(&args.file)?);
+    let file = open_input(&args.file)?;
+    let mut reader = io::BufReader::new(file);
+
+    let mut state = OutputState::new(args, linesplit)?;
+
+    // lines are split as raw bytes; the input need not be text
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        let n_read = reader.read_until(b'\n', &mut buffer)?;
+        if n_read == 0 {
+            break;
+        }
+
+        state.open_output()?;
+
+        state.write(&buffer)?;
+
+        state.incr_output(1)?;
     }
+
+    state.close_output()
+}
+
+/// Split into pieces of whole lines, at most `bytesplit` bytes each.  A
+/// line longer than that is split across pieces of its own.
+fn split_by_line_bytes(args: &Args, bytesplit: &str) -> io::Result<()> {
+    let boundary = parse_byte_count(bytesplit)?;
+
+    let file = open_input(&args.file)?;
     let mut reader = io::BufReader::new(file);
 
-    let mut state = OutputState::new(&args.prefix, linesplit, args.suffix_len);
+    let mut state = OutputState::new(args, boundary)?;
 
+    let mut buffer = Vec::new();
     loop {
-        let mut buffer = String::new();
-        let n_read = reader.read_line(&mut buffer)?;
+        buffer.clear();
+        let n_read = reader.read_until(b'\n', &mut buffer)?;
         if n_read == 0 {
             break;
         }
 
+        if state.count > 0 && state.count + buffer.len() as u64 > boundary {
+            state.close_output()?;
+        }
+
+        state.output_bytes(&buffer)?;
+    }
+
+    state.close_output()
+}
+
+/// Split into `n_chunks` pieces of equal size, the last one taking any
+/// remainder.
+fn split_into_chunks(args: &Args, n_chunks: u64) -> io::Result<()> {
+    let (mut file, size) = open_sized_input(&args.file)?;
+    let chunk_size = size / n_chunks;
+
+    let mut state = OutputState::new(args, u64::MAX)?;
+
+    for i in 0..n_chunks {
+        let len = if i == n_chunks - 1 {
+            size - chunk_size * i
+        } else {
+            chunk_size
+        };
+
         state.open_output()?;
+        io::copy(&mut file.by_ref().take(len), state.outf.as_mut().unwrap())?;
+        state.close_output()?;
+    }
 
-        state.write(buffer.as_ref())?;
+    Ok(())
+}
+
+/// Split into `n_chunks` pieces of roughly equal size, without splitting
+/// lines: each line goes to the piece in which it starts.
+fn split_into_line_chunks(args: &Args, n_chunks: u64) -> io::Result<()> {
+    let (file, size) = open_sized_input(&args.file)?;
+    let chunk_size = size / n_chunks;
+    let mut reader = io::BufReader::new(file);
 
-        state.incr_output(1);
+    let mut state = OutputState::new(args, u64::MAX)?;
+    let mut chunk = 0;
+    let mut offset: u64 = 0;
+    state.open_output()?;
+
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        let n_read = reader.read_until(b'\n', &mut buffer)?;
+        if n_read == 0 {
+            break;
+        }
+
+        let target = match offset.checked_div(chunk_size) {
+            Some(n) => cmp::min(n, n_chunks - 1),
+            None => n_chunks - 1,
+        };
+        while chunk < target {
+            state.close_output()?;
+            state.open_output()?;
+            chunk += 1;
+        }
+
+        state.write(&buffer)?;
+        offset += n_read as u64;
+    }
+
+    // every piece is created, even if empty
+    while chunk < n_chunks - 1 {
+        state.close_output()?;
+        state.open_output()?;
+        chunk += 1;
+    }
+
+    state.close_output()
+}
+
+/// Split into `n_chunks` pieces, distributing lines among them in turn.
+fn split_round_robin(args: &Args, n_chunks: u64) -> io::Result<()> {
+    let file = open_input(&args.file)?;
+    let mut reader = io::BufReader::new(file);
+
+    let mut names = Names::new(args)?;
+    let mut sinks = Vec::new();
+    for _ in 0..n_chunks {
+        sinks.push(open_sink(&names.next()?, args.filter.as_deref())?);
+    }
+
+    let mut buffer = Vec::new();
+    let mut i = 0;
+    loop {
+        buffer.clear();
+        let n_read = reader.read_until(b'\n', &mut buffer)?;
+        if n_read == 0 {
+            break;
+        }
+
+        sinks[i].write_all(&buffer)?;
+        i = (i + 1) % sinks.len();
+    }
+
+    for sink in sinks {
+        sink.finish()?;
     }
 
     Ok(())
 }
 
+fn split_by_number(args: &Args, spec: &str) -> io::Result<()> {
+    let (kind, count) = match spec.split_once('/') {
+        Some((kind, count)) => (kind, count),
+        None => ("", spec),
+    };
+
+    let n_chunks = match count.parse::<u64>() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            return Err(io::Error::other(format!(
+                "{}: {}",
+                gettext("invalid number of chunks"),
+                spec
+            )))
+        }
+    };
+    if Names::new(args)?.remaining() < n_chunks {
+        return Err(io::Error::other(gettext(
+            "too many chunks for the suffix length",
+        )));
+    }
+
+    match kind {
+        "" => split_into_chunks(args, n_chunks),
+        "l" => split_into_line_chunks(args, n_chunks),
+        "r" => split_round_robin(args, n_chunks),
+        _ => Err(io::Error::other(format!(
+            "{}: {}",
+            gettext("invalid number of chunks"),
+            spec
+        ))),
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line arguments
     let mut args = Args::parse();
@@ -255,14 +576,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
-    if args.lines.is_none() && args.bytes.is_none() {
+    if args.lines.is_none()
+        && args.bytes.is_none()
+        && args.line_bytes.is_none()
+        && args.number.is_none()
+    {
         args.lines = Some(1000);
     }
 
-    if args.lines.is_some() {
-        split_by_lines(&args, args.lines.unwrap())?;
+    let res = if let Some(lines) = args.lines {
+        split_by_lines(&args, lines)
+    } else if let Some(bytes) = &args.bytes {
+        split_by_bytes(&args, bytes)
+    } else if let Some(line_bytes) = &args.line_bytes {
+        split_by_line_bytes(&args, line_bytes)
     } else {
-        split_by_bytes(&args, args.bytes.clone().unwrap())?;
+        split_by_number(&args, args.number.as_deref().unwrap())
+    };
+
+    if let Err(e) = res {
+        eprintln!("split: {}", e);
+        std::process::exit(1);
     }
 
     Ok(())
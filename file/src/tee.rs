@@ -11,12 +11,29 @@ extern crate clap;
 extern crate libc;
 extern crate plib;
 
-use clap::Parser;
-use gettextrs::{bind_textdomain_codeset, textdomain};
-use libc::{signal, SIGINT, SIG_IGN};
+use clap::{Parser, ValueEnum};
+use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
+use libc::{signal, SIGINT, SIGPIPE, SIG_DFL, SIG_IGN};
 use plib::PROJECT_NAME;
 use std::fs::{File, OpenOptions};
 use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+/// What to do when writing to an output fails.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq)]
+enum OutputError {
+    /// Diagnose errors writing to any output.
+    Warn,
+
+    /// Diagnose errors writing to any output other than a pipe.
+    WarnNopipe,
+
+    /// Exit on errors writing to any output.
+    Exit,
+
+    /// Exit on errors writing to any output other than a pipe.
+    ExitNopipe,
+}
 
 /// tee - duplicate standard input
 #[derive(Parser, Debug)]
@@ -30,6 +47,21 @@ struct Args {
     #[arg(short, long)]
     ignore: bool,
 
+    /// Diagnose errors writing to outputs other than pipes (same as --output-error=warn-nopipe).
+    #[arg(short = 'p')]
+    pipe_errors: bool,
+
+    /// Set the behavior on errors writing to an output.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "warn-nopipe"
+    )]
+    output_error: Option<OutputError>,
+
     /// One or more output files.
     files: Vec<String>,
 }
@@ -41,17 +73,28 @@ struct TeeFile {
 
 struct TeeInfo {
     outputs: Vec<TeeFile>,
+    output_error: Option<OutputError>,
+    failed: bool,
 }
 
 impl TeeInfo {
-    fn new() -> TeeInfo {
+    fn new(output_error: Option<OutputError>) -> TeeInfo {
         TeeInfo {
             outputs: Vec::new(),
+            output_error,
+            failed: false,
         }
     }
 }
 
-fn open_outputs(args: &Args, info: &mut TeeInfo) -> io::Result<()> {
+fn open_outputs(args: &Args, info: &mut TeeInfo) {
+    // standard output is used unbuffered, like the files, so that
+    // nothing is held back from it
+    info.outputs.push(TeeFile {
+        filename: gettext("standard output"),
+        f: unsafe { File::from_raw_fd(libc::STDOUT_FILENO) },
+    });
+
     for filename in &args.files {
         let f_res = OpenOptions::new()
             .read(false)
@@ -61,10 +104,12 @@ fn open_outputs(args: &Args, info: &mut TeeInfo) -> io::Result<()> {
             .append(args.append)
             .open(filename);
 
+        // a file that cannot be opened is diagnosed and skipped; the
+        // others still receive the input
         match f_res {
             Err(e) => {
-                eprintln!("{}: {}", filename, e);
-                return Err(e);
+                eprintln!("tee: {}: {}", filename, e);
+                info.failed = true;
             }
             Ok(f) => {
                 info.outputs.push(TeeFile {
@@ -74,31 +119,218 @@ fn open_outputs(args: &Args, info: &mut TeeInfo) -> io::Result<()> {
             }
         }
     }
+}
 
-    Ok(())
+/// Handle an error writing to an output, which is then dropped.
+fn output_failed(info: &mut TeeInfo, filename: &str, e: io::Error) {
+    let is_pipe = e.kind() == io::ErrorKind::BrokenPipe;
+    let quiet = is_pipe
+        && matches!(
+            info.output_error,
+            Some(OutputError::WarnNopipe) | Some(OutputError::ExitNopipe)
+        );
+    if quiet {
+        return;
+    }
+
+    eprintln!("tee: {}: {}", filename, e);
+    info.failed = true;
+
+    if matches!(
+        info.output_error,
+        Some(OutputError::Exit) | Some(OutputError::ExitNopipe)
+    ) {
+        std::process::exit(1);
+    }
 }
 
-fn tee_stdin(info: &mut TeeInfo) -> io::Result<()> {
-    let mut buffer = [0; plib::BUFSZ];
+/// One output's own pipe, through which tee_spliced feeds it.
+#[cfg(target_os = "linux")]
+struct Branch {
+    reader: File,
+    writer: File,
+
+    // splice(2) works from the pipe to the output
+    spliceable: bool,
+
+    // bytes that tee(2) put in the pipe beyond the stretch of input
+    // being copied, to be discarded once that is drained
+    excess: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl Branch {
+    fn new() -> io::Result<Branch> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Branch {
+            reader: unsafe { File::from_raw_fd(fds[0]) },
+            writer: unsafe { File::from_raw_fd(fds[1]) },
+            spliceable: true,
+            excess: 0,
+        })
+    }
+
+    /// Move `len` bytes from the pipe to the output, then empty the pipe
+    /// of any excess.
+    fn drain(&mut self, output: &mut File, len: usize) -> io::Result<()> {
+        let mut left = len;
+        while left > 0 {
+            if self.spliceable {
+                match plib::copy::splice(self.reader.as_raw_fd(), output.as_raw_fd(), left) {
+                    Ok(n) => left -= n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+
+                    // not to a terminal, for one; carry on through a buffer
+                    Err(e) if e.raw_os_error() == Some(libc::EINVAL) => self.spliceable = false,
+                    Err(e) => return Err(e),
+                }
+            } else {
+                let mut buffer = vec![0; left.min(plib::copy::COPY_BUFSZ)];
+                let n = self.reader.read(&mut buffer)?;
+                output.write_all(&buffer[..n])?;
+                left -= n;
+            }
+        }
 
+        // the next stretch of input starts where this one ended, so it
+        // will be duplicated into the pipe again
+        let mut buffer = vec![0; self.excess.min(plib::copy::COPY_BUFSZ)];
+        while self.excess > 0 {
+            let want = self.excess.min(buffer.len());
+            let n = self.reader.read(&mut buffer[..want])?;
+            self.excess -= n;
+        }
+        Ok(())
+    }
+}
+
+/// Retry a system call interrupted by a signal.
+#[cfg(target_os = "linux")]
+fn retry(call: impl Fn() -> io::Result<usize>) -> io::Result<usize> {
     loop {
-        let n_read_res = io::stdin().read(&mut buffer[..]);
-        if let Err(e) = n_read_res {
-            eprintln!("stdin: {}", e);
-            return Err(e);
+        match call() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
+
+/// Put the next stretch of input into every branch pipe, returning its
+/// length, or 0 at the end of the input.
+#[cfg(target_os = "linux")]
+fn fill_branches(stdin_fd: RawFd, branches: &mut [Branch]) -> io::Result<usize> {
+    let (last, others) = branches.split_last_mut().unwrap();
+    let last_fd = last.writer.as_raw_fd();
+    if others.is_empty() {
+        return retry(|| plib::copy::splice(stdin_fd, last_fd, plib::copy::COPY_BUFSZ));
+    }
+
+    // tee(2) takes only as much as fits in each pipe, which need not be
+    // the same for all of them.  The input is consumed only by the final
+    // splice, so the stretch copied is the least that any pipe took.
+    let mut len = plib::copy::COPY_BUFSZ;
+    for branch in others.iter_mut() {
+        let writer_fd = branch.writer.as_raw_fd();
+        branch.excess = retry(|| plib::copy::tee(stdin_fd, writer_fd, plib::copy::COPY_BUFSZ))?;
+        if branch.excess == 0 {
+            return Ok(0);
+        }
+        len = len.min(branch.excess);
+    }
+
+    // the last pipe may be the smallest, so it is filled without waiting
+    // for room, and what it takes cuts the stretch shorter still
+    let mut moved = 0;
+    while moved < len {
+        match plib::copy::splice_nonblock(stdin_fd, last_fd, len - moved) {
+            Ok(0) => break,
+            Ok(n) => moved += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && moved > 0 => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    for branch in others.iter_mut() {
+        branch.excess -= moved;
+    }
+    Ok(moved)
+}
+
+/// Copy a piped stdin to the outputs without the data passing through
+/// user space.  Each output gets its own pipe: the input is duplicated
+/// into all but the last of these with tee(2), and moved into the last
+/// with splice(2), and then each pipe is drained into its output.
+#[cfg(target_os = "linux")]
+fn tee_spliced(info: &mut TeeInfo) -> io::Result<()> {
+    let stdin_fd = io::stdin().as_raw_fd();
+    let mut branches = Vec::new();
+    for _ in 0..info.outputs.len() {
+        branches.push(Branch::new()?);
+    }
+
+    while !info.outputs.is_empty() {
+        let len = match fill_branches(stdin_fd, &mut branches) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) => {
+                eprintln!("tee: stdin: {}", e);
+                return Err(e);
+            }
+        };
+
+        let mut i = 0;
+        while i < info.outputs.len() {
+            match branches[i].drain(&mut info.outputs[i].f, len) {
+                Ok(()) => i += 1,
+                Err(e) => {
+                    branches.remove(i);
+                    let output = info.outputs.remove(i);
+                    output_failed(info, &output.filename, e);
+                }
+            }
         }
-        let n_read = n_read_res.unwrap();
+    }
+
+    Ok(())
+}
+
+fn tee_stdin(info: &mut TeeInfo) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    if plib::copy::is_pipe(io::stdin().as_raw_fd()) && !info.outputs.is_empty() {
+        return tee_spliced(info);
+    }
+
+    let mut buffer = vec![0; plib::copy::COPY_BUFSZ];
+
+    // stop early only once every output is gone
+    while !info.outputs.is_empty() {
+        let n_read = match io::stdin().read(&mut buffer[..]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                eprintln!("tee: stdin: {}", e);
+                return Err(e);
+            }
+        };
         if n_read == 0 {
             break;
         }
 
         let bufslice = &buffer[0..n_read];
 
-        for output in &mut info.outputs {
-            let res = output.f.write_all(bufslice);
-            if let Err(e) = res {
-                eprintln!("{}: {}", output.filename, e);
-                return Err(e);
+        let mut i = 0;
+        while i < info.outputs.len() {
+            match info.outputs[i].f.write_all(bufslice) {
+                Ok(()) => i += 1,
+                Err(e) => {
+                    let output = info.outputs.remove(i);
+                    output_failed(info, &output.filename, e);
+                }
             }
         }
     }
@@ -108,21 +340,36 @@ fn tee_stdin(info: &mut TeeInfo) -> io::Result<()> {
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line arguments
-    let args = Args::parse();
+    let mut args = Args::parse();
 
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
-    if args.ignore {
-        unsafe {
+    if args.pipe_errors && args.output_error.is_none() {
+        args.output_error = Some(OutputError::WarnNopipe);
+    }
+
+    unsafe {
+        if args.ignore {
             signal(SIGINT, SIG_IGN);
         }
+
+        // Without an --output-error mode, writing to a closed pipe
+        // kills tee with SIGPIPE, as usual; with one, the write fails
+        // with EPIPE and is handled like any other error.
+        if args.output_error.is_none() {
+            signal(SIGPIPE, SIG_DFL);
+        }
     }
 
-    let mut state = TeeInfo::new();
+    let mut state = TeeInfo::new(args.output_error);
 
-    open_outputs(&args, &mut state)?;
-    tee_stdin(&mut state)?;
+    open_outputs(&args, &mut state);
+    let res = tee_stdin(&mut state);
+
+    if res.is_err() || state.failed {
+        std::process::exit(1);
+    }
 
     Ok(())
 }
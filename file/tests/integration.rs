@@ -0,0 +1,473 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::testing::{bin_path, run_in, run_test, temp_dir, TestPlan};
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_cp_preserve() {
+    let dir = temp_dir("cp-preserve");
+    let src = dir.join("src");
+    fs::write(&src, "data\n").unwrap();
+    fs::set_permissions(&src, fs::Permissions::from_mode(0o640)).unwrap();
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let times = fs::FileTimes::new().set_accessed(mtime).set_modified(mtime);
+    fs::File::options()
+        .write(true)
+        .open(&src)
+        .unwrap()
+        .set_times(times)
+        .unwrap();
+
+    let output = run_in(&dir, "cp", &["-p", "src", "dst"]);
+    assert!(output.status.success());
+
+    let md = fs::metadata(dir.join("dst")).unwrap();
+    assert_eq!(fs::read_to_string(dir.join("dst")).unwrap(), "data\n");
+    assert_eq!(md.mode() & 0o7777, 0o640);
+    assert_eq!(md.modified().unwrap(), mtime);
+    assert_eq!(md.accessed().unwrap(), mtime);
+
+    // without -p, the copy is new
+    let output = run_in(&dir, "cp", &["src", "plain"]);
+    assert!(output.status.success());
+    assert_ne!(
+        fs::metadata(dir.join("plain")).unwrap().modified().unwrap(),
+        mtime
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cp_recursive() {
+    let dir = temp_dir("cp-recursive");
+    fs::create_dir_all(dir.join("a/sub")).unwrap();
+    fs::write(dir.join("a/file"), "file\n").unwrap();
+    fs::write(dir.join("a/sub/deep"), "deep\n").unwrap();
+    std::os::unix::fs::symlink("file", dir.join("a/link")).unwrap();
+    let fifo = std::ffi::CString::new(dir.join("a/fifo").to_str().unwrap()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+    std::os::unix::fs::symlink("a", dir.join("alink")).unwrap();
+
+    // -R copies symlinks as symlinks and recreates FIFOs
+    let output = run_in(&dir, "cp", &["-R", "a", "b"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(dir.join("b/sub/deep")).unwrap(),
+        "deep\n"
+    );
+    assert_eq!(
+        fs::read_link(dir.join("b/link")).unwrap(),
+        Path::new("file")
+    );
+    assert!(fs::symlink_metadata(dir.join("b/fifo"))
+        .unwrap()
+        .file_type()
+        .is_fifo());
+
+    // a new directory takes the source's mode, without -p as well
+    fs::set_permissions(dir.join("a/sub"), fs::Permissions::from_mode(0o500)).unwrap();
+    let output = run_in(&dir, "cp", &["-R", "a", "modes"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::metadata(dir.join("modes/sub")).unwrap().mode() & 0o7777,
+        0o500
+    );
+    fs::set_permissions(dir.join("modes/sub"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::set_permissions(dir.join("a/sub"), fs::Permissions::from_mode(0o755)).unwrap();
+
+    // missing parents of the target are not created
+    let output = run_in(&dir, "cp", &["-R", "a", "missing/b"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!dir.join("missing").exists());
+
+    // -L follows symlinks found in the hierarchy
+    let output = run_in(&dir, "cp", &["-R", "-L", "a", "c"]);
+    assert!(output.status.success());
+    let md = fs::symlink_metadata(dir.join("c/link")).unwrap();
+    assert!(md.is_file());
+    assert_eq!(fs::read_to_string(dir.join("c/link")).unwrap(), "file\n");
+
+    // -H follows only symlinks named on the command line
+    let output = run_in(&dir, "cp", &["-R", "-H", "alink", "d"]);
+    assert!(output.status.success());
+    assert!(fs::symlink_metadata(dir.join("d")).unwrap().is_dir());
+    assert!(fs::symlink_metadata(dir.join("d/link"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+
+    // -P, the default under -R, copies a named symlink as it is
+    let output = run_in(&dir, "cp", &["-R", "alink", "e"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_link(dir.join("e")).unwrap(), Path::new("a"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cp_errors() {
+    let dir = temp_dir("cp-errors");
+    fs::create_dir(dir.join("a")).unwrap();
+    fs::write(dir.join("a/file"), "file\n").unwrap();
+    fs::write(dir.join("src"), "src\n").unwrap();
+
+    // a directory cannot be copied into itself
+    let output = run_in(&dir, "cp", &["-R", "a", "a/inside"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!dir.join("a/inside").exists());
+
+    let output = run_in(&dir, "cp", &["src", "src"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(dir.join("src")).unwrap(), "src\n");
+
+    // a failed operand does not stop the others
+    fs::create_dir(dir.join("out")).unwrap();
+    let output = run_in(&dir, "cp", &["missing", "src", "out"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(dir.join("out/src")).unwrap(), "src\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cp_existing_target() {
+    let dir = temp_dir("cp-existing");
+    fs::write(dir.join("src"), "new\n").unwrap();
+    fs::set_permissions(dir.join("src"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(dir.join("dst"), "old contents\n").unwrap();
+    fs::set_permissions(dir.join("dst"), fs::Permissions::from_mode(0o600)).unwrap();
+    fs::hard_link(dir.join("dst"), dir.join("link")).unwrap();
+    let ino = fs::metadata(dir.join("dst")).unwrap().ino();
+
+    // the target is written in place, keeping its inode, links and mode
+    let output = run_in(&dir, "cp", &["src", "dst"]);
+    assert!(output.status.success());
+    let md = fs::metadata(dir.join("dst")).unwrap();
+    assert_eq!(md.ino(), ino);
+    assert_eq!(md.mode() & 0o7777, 0o600);
+    assert_eq!(fs::read_to_string(dir.join("link")).unwrap(), "new\n");
+
+    // -p takes the mode of the source
+    let output = run_in(&dir, "cp", &["-p", "src", "dst"]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::metadata(dir.join("dst")).unwrap().mode() & 0o7777,
+        0o755
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The lines of dd's statistics, less the last one, which has timings.
+fn dd_stats(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    lines[..lines.len() - 1].join("\n")
+}
+
+#[test]
+fn test_dd_block_unblock() {
+    let dir = temp_dir("dd-block");
+    fs::write(dir.join("lines"), "ab\ncdefgh\n\nxyz\n").unwrap();
+
+    // each line is padded with spaces, or cut, to cbs bytes
+    let output = run_in(&dir, "dd", &["if=lines", "conv=block", "cbs=4"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"ab  cdef    xyz ");
+    assert_eq!(
+        dd_stats(&output),
+        "0+1 records in\n0+1 records out\n1 truncated record"
+    );
+
+    // and back, with trailing spaces dropped
+    fs::write(dir.join("blocks"), "ab  cdef    xyz ").unwrap();
+    let output = run_in(&dir, "dd", &["if=blocks", "conv=unblock", "cbs=4"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"ab\ncdef\n\nxyz\n");
+
+    let output = run_in(&dir, "dd", &["if=lines", "conv=ucase,swab"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"BAC\nEDGF\nHX\nZY\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dd_reblock() {
+    let dir = temp_dir("dd-reblock");
+    fs::write(dir.join("in"), "0123456789").unwrap();
+
+    // input blocks are gathered into output blocks of obs bytes
+    let output = run_in(&dir, "dd", &["if=in", "ibs=3", "obs=4"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"0123456789");
+    assert_eq!(dd_stats(&output), "3+1 records in\n2+1 records out");
+
+    // with bs=, each input block is written as it was read
+    let output = run_in(&dir, "dd", &["if=in", "bs=3", "count=2"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"012345");
+    assert_eq!(dd_stats(&output), "2+0 records in\n2+0 records out");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_dd_skip_seek() {
+    let dir = temp_dir("dd-skip");
+    fs::write(dir.join("in"), "0123456789").unwrap();
+
+    let output = run_in(&dir, "dd", &["if=in", "ibs=2", "skip=2", "count=2"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"4567");
+
+    // the output is kept up to the seek point, and truncated after
+    fs::write(dir.join("out"), "XXXXXXXXXXXX").unwrap();
+    let output = run_in(
+        &dir,
+        "dd",
+        &["if=in", "of=out", "bs=2", "seek=1", "count=2"],
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("out")).unwrap(), "XX0123");
+
+    // unless conv=notrunc
+    fs::write(dir.join("out"), "XXXXXXXXXXXX").unwrap();
+    let output = run_in(
+        &dir,
+        "dd",
+        &[
+            "if=in",
+            "of=out",
+            "bs=2",
+            "seek=1",
+            "count=2",
+            "conv=notrunc",
+        ],
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("out")).unwrap(), "XX0123XXXXXX");
+
+    // skipping past the end of a file is diagnosed
+    let output = run_in(&dir, "dd", &["if=in", "bs=4", "skip=5"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot skip past end of input"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The names in `dir` that start with `prefix`, in order.
+fn outputs(dir: &Path, prefix: &str) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_split_suffixes() {
+    let dir = temp_dir("split-suffixes");
+    let lines: String = (1..=30).map(|i| format!("{}\n", i)).collect();
+    fs::write(dir.join("in"), &lines).unwrap();
+
+    // with -a 1 there are 26 names; the rest of the input has nowhere to go
+    let output = run_in(&dir, "split", &["-a", "1", "-l", "1", "in"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("suffixes exhausted"));
+    let names = outputs(&dir, "x");
+    assert_eq!(names.len(), 26);
+    assert_eq!(names.first().unwrap(), "xa");
+    assert_eq!(names.last().unwrap(), "xz");
+    assert_eq!(fs::read_to_string(dir.join("xz")).unwrap(), "26\n");
+
+    // lines need not be valid UTF-8
+    fs::write(dir.join("bin"), b"a\xff\nb\xfe\nc\n").unwrap();
+    let output = run_in(&dir, "split", &["-l", "2", "bin", "b"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(dir.join("baa")).unwrap(), b"a\xff\nb\xfe\n");
+    assert_eq!(fs::read(dir.join("bab")).unwrap(), b"c\n");
+
+    // -b takes the POSIX k suffix
+    fs::write(dir.join("zeros"), vec![0u8; 3000]).unwrap();
+    let output = run_in(&dir, "split", &["-b", "1k", "zeros", "k"]);
+    assert!(output.status.success());
+    let sizes: Vec<u64> = outputs(&dir, "k")
+        .iter()
+        .map(|name| fs::metadata(dir.join(name)).unwrap().len())
+        .collect();
+    assert_eq!(sizes, [1024, 1024, 952]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_split_chunks() {
+    let dir = temp_dir("split-chunks");
+    let lines: String = (1..=30).map(|i| format!("{}\n", i)).collect();
+    fs::write(dir.join("in"), &lines).unwrap();
+
+    // -n l/N makes N chunks of about equal size without breaking lines
+    let output = run_in(&dir, "split", &["-n", "l/3", "in", "l"]);
+    assert!(output.status.success());
+    let chunks: Vec<String> = outputs(&dir, "l")
+        .iter()
+        .map(|name| fs::read_to_string(dir.join(name)).unwrap())
+        .collect();
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(chunks.concat(), lines);
+    assert!(chunks.iter().all(|c| c.ends_with('\n')));
+    assert!(chunks[1].starts_with("13\n"));
+
+    // -n r/N deals the lines out in turn
+    let output = run_in(&dir, "split", &["-n", "r/3", "in", "r"]);
+    assert!(output.status.success());
+    assert_eq!(outputs(&dir, "r").len(), 3);
+    let expected: String = (1..=30).step_by(3).map(|i| format!("{}\n", i)).collect();
+    assert_eq!(fs::read_to_string(dir.join("raa")).unwrap(), expected);
+
+    // -C puts as many whole lines as fit in each file
+    fs::write(dir.join("short"), "aaaa\nbb\ncccccc\nd\n").unwrap();
+    let output = run_in(&dir, "split", &["-C", "7", "short", "c"]);
+    assert!(output.status.success());
+    let chunks: Vec<String> = outputs(&dir, "c")
+        .iter()
+        .map(|name| fs::read_to_string(dir.join(name)).unwrap())
+        .collect();
+    assert_eq!(chunks, ["aaaa\n", "bb\n", "cccccc\n", "d\n"]);
+
+    // --filter runs each chunk through the shell, with $FILE set
+    let output = run_in(
+        &dir,
+        "split",
+        &["-l", "10", "--filter=wc -l > $FILE.n", "in", "f"],
+    );
+    assert!(output.status.success());
+    assert_eq!(outputs(&dir, "f"), ["faa.n", "fab.n", "fac.n"]);
+    assert_eq!(fs::read_to_string(dir.join("fab.n")).unwrap().trim(), "10");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Run a shell pipeline in `dir`, with `$TEE` naming the tee under test.
+fn sh_in(dir: &Path, script: &str) -> Output {
+    Command::new("sh")
+        .args(["-c", script])
+        .env("TEE", bin_path("tee"))
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_tee_outputs() {
+    let dir = temp_dir("tee-outputs");
+    fs::write(dir.join("a"), "one\n").unwrap();
+
+    // -a appends, and the input is also copied to standard output
+    let output = sh_in(&dir, "echo two | \"$TEE\" -a a b");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"two\n");
+    assert_eq!(fs::read_to_string(dir.join("a")).unwrap(), "one\ntwo\n");
+    assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "two\n");
+
+    // a file that cannot be opened does not stop the others
+    let output = sh_in(&dir, "echo x | \"$TEE\" missing/f c");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stdout, b"x\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("missing/f"));
+    assert_eq!(fs::read_to_string(dir.join("c")).unwrap(), "x\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_tee_output_error() {
+    let dir = temp_dir("tee-output-error");
+
+    // with -p, a closed pipe is not an error, and the file gets everything
+    let output = sh_in(
+        &dir,
+        "yes | head -c 200000 | { \"$TEE\" -p d; echo $? >status; } \
+         | head -c 1 >/dev/null",
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("status")).unwrap(), "0\n");
+    assert_eq!(fs::metadata(dir.join("d")).unwrap().len(), 200000);
+
+    // --output-error=exit gives up at the first failed write
+    let output = sh_in(
+        &dir,
+        "yes | head -c 200000 | { \"$TEE\" --output-error=exit e; echo $? >status; } \
+         | head -c 1 >/dev/null",
+    );
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("status")).unwrap(), "1\n");
+    assert!(fs::metadata(dir.join("e")).unwrap().len() < 200000);
+
+    // --output-error=warn-nopipe, as -p, still reports other failures
+    let output = sh_in(
+        &dir,
+        "echo x | \"$TEE\" --output-error=warn-nopipe missing/f",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(output.stdout, b"x\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn cat_test(args: &[&str], test_data: &str, expected_output: &str) {
+    run_test(TestPlan {
+        cmd: String::from("cat"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(test_data),
+        expected_out: String::from(expected_output),
+        expected_exit_code: 0,
+    });
+}
+
+const CAT_INPUT: &str = "a\tb\n\n\n\nc\x01\x7f\u{e9}\n";
+
+#[test]
+fn test_cat_numbering() {
+    cat_test(
+        &["-n"],
+        CAT_INPUT,
+        "     1\ta\tb\n     2\t\n     3\t\n     4\t\n     5\tc\x01\x7f\u{e9}\n",
+    );
+    cat_test(
+        &["-b"],
+        CAT_INPUT,
+        "     1\ta\tb\n\n\n\n     2\tc\x01\x7f\u{e9}\n",
+    );
+    cat_test(&["-s"], CAT_INPUT, "a\tb\n\nc\x01\x7f\u{e9}\n");
+    cat_test(
+        &["-s", "-n"],
+        CAT_INPUT,
+        "     1\ta\tb\n     2\t\n     3\tc\x01\x7f\u{e9}\n",
+    );
+}
+
+#[test]
+fn test_cat_show() {
+    cat_test(&["-v"], CAT_INPUT, "a\tb\n\n\n\nc^A^?M-CM-)\n");
+    cat_test(&["-E"], CAT_INPUT, "a\tb$\n$\n$\n$\nc\x01\x7f\u{e9}$\n");
+    cat_test(&["-T"], CAT_INPUT, "a^Ib\n\n\n\nc\x01\x7f\u{e9}\n");
+    cat_test(&["-A"], CAT_INPUT, "a^Ib$\n$\n$\n$\nc^A^?M-CM-)$\n");
+    cat_test(&["-u"], CAT_INPUT, CAT_INPUT);
+}
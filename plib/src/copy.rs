@@ -0,0 +1,364 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+// Copying between file descriptors without passing the data through
+// user space, where the kernel allows it: copy_file_range(2) between
+// regular files, splice(2) to or from a pipe, and sendfile(2) from a
+// regular file to anything else.  Elsewhere, and whenever the kernel
+// declines, data is copied through a large buffer.
+//
+
+use std::fmt;
+use std::io;
+use std::os::unix::io::RawFd;
+
+/// Buffer size for copies that go through user space.
+pub const COPY_BUFSZ: usize = 128 * 1024;
+
+/// A failed copy, telling which side failed.
+#[derive(Debug)]
+pub enum CopyError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+impl fmt::Display for CopyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CopyError::Read(e) => e.fmt(f),
+            CopyError::Write(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<CopyError> for io::Error {
+    fn from(e: CopyError) -> io::Error {
+        match e {
+            CopyError::Read(e) => e,
+            CopyError::Write(e) => e,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FdKind {
+    Regular,
+    Pipe,
+    Other,
+}
+
+fn fd_kind(fd: RawFd) -> FdKind {
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut st) } != 0 {
+        return FdKind::Other;
+    }
+
+    match st.st_mode & libc::S_IFMT {
+        libc::S_IFREG => FdKind::Regular,
+        libc::S_IFIFO => FdKind::Pipe,
+        _ => FdKind::Other,
+    }
+}
+
+/// Is `fd` a pipe or FIFO?
+pub fn is_pipe(fd: RawFd) -> bool {
+    fd_kind(fd) == FdKind::Pipe
+}
+
+/// Did a zero-copy call fail only because it does not apply to these
+/// descriptors, so that copying through a buffer will work instead?
+#[cfg(target_os = "linux")]
+fn is_unsupported(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::EINVAL)
+            | Some(libc::ENOSYS)
+            | Some(libc::EXDEV)
+            | Some(libc::EOPNOTSUPP)
+            | Some(libc::EBADF)
+            | Some(libc::ETXTBSY)
+    )
+}
+
+// a zero-copy system call, taking the input, output and length
+#[cfg(target_os = "linux")]
+type CopyCall = fn(RawFd, RawFd, usize) -> io::Result<usize>;
+
+// the most to ask of the kernel in one call
+#[cfg(target_os = "linux")]
+const CHUNK: usize = 1 << 30;
+
+#[cfg(target_os = "linux")]
+fn check(ret: isize) -> io::Result<usize> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// splice(2) up to `len` bytes from `fd_in` to `fd_out`, one of which
+/// must be a pipe, at their current offsets.
+#[cfg(target_os = "linux")]
+pub fn splice(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+    let ret = unsafe {
+        libc::splice(
+            fd_in,
+            std::ptr::null_mut(),
+            fd_out,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE,
+        )
+    };
+    check(ret)
+}
+
+/// splice(2), as above, but failing with EAGAIN rather than waiting
+/// for a pipe to have data or room.
+#[cfg(target_os = "linux")]
+pub fn splice_nonblock(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+    let ret = unsafe {
+        libc::splice(
+            fd_in,
+            std::ptr::null_mut(),
+            fd_out,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    check(ret)
+}
+
+/// tee(2): duplicate up to `len` bytes from the pipe `fd_in` into the
+/// pipe `fd_out`, without consuming them.
+#[cfg(target_os = "linux")]
+pub fn tee(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+    let ret = unsafe { libc::tee(fd_in, fd_out, len, 0) };
+    check(ret)
+}
+
+#[cfg(target_os = "linux")]
+fn copy_file_range(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+    let ret = unsafe {
+        libc::copy_file_range(
+            fd_in,
+            std::ptr::null_mut(),
+            fd_out,
+            std::ptr::null_mut(),
+            len,
+            0,
+        )
+    };
+    check(ret)
+}
+
+#[cfg(target_os = "linux")]
+fn sendfile(fd_in: RawFd, fd_out: RawFd, len: usize) -> io::Result<usize> {
+    let ret = unsafe { libc::sendfile(fd_out, fd_in, std::ptr::null_mut(), len) };
+    check(ret)
+}
+
+/// Copy with one zero-copy call, repeated until the input ends.  An
+/// error from the call cannot be pinned on either side alone, so it is
+/// put down to the output, unless the call is simply unsupported, in
+/// which case Ok(None) lets the caller carry on some other way.
+#[cfg(target_os = "linux")]
+fn copy_with(
+    call: CopyCall,
+    fd_in: RawFd,
+    fd_out: RawFd,
+    total: &mut u64,
+) -> Result<Option<()>, CopyError> {
+    loop {
+        match call(fd_in, fd_out, CHUNK) {
+            Ok(0) => return Ok(Some(())),
+            Ok(n) => *total += n as u64,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) if is_unsupported(&e) => return Ok(None),
+            Err(e) => return Err(CopyError::Write(e)),
+        }
+    }
+}
+
+/// Copy through a buffer, from the current offsets until the input ends.
+pub fn copy_buffered(fd_in: RawFd, fd_out: RawFd) -> Result<u64, CopyError> {
+    let mut buffer = vec![0u8; COPY_BUFSZ];
+    let mut total = 0;
+
+    loop {
+        let n_read = unsafe {
+            libc::read(
+                fd_in,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if n_read < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(CopyError::Read(e));
+        }
+        if n_read == 0 {
+            break;
+        }
+
+        write_all_fd(fd_out, &buffer[..n_read as usize]).map_err(CopyError::Write)?;
+        total += n_read as u64;
+    }
+
+    Ok(total)
+}
+
+/// Write all of `buf` to `fd`.
+pub fn write_all_fd(fd: RawFd, buf: &[u8]) -> io::Result<()> {
+    let mut written = 0;
+    while written < buf.len() {
+        let n = unsafe {
+            libc::write(
+                fd,
+                buf[written..].as_ptr() as *const libc::c_void,
+                buf.len() - written,
+            )
+        };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::WriteZero));
+        }
+        written += n as usize;
+    }
+    Ok(())
+}
+
+/// Copy everything from `fd_in`, starting at its current offset, to
+/// `fd_out`, returning the number of bytes copied.  Uses the fastest
+/// method the two descriptors allow.
+pub fn copy_fd(fd_in: RawFd, fd_out: RawFd) -> Result<u64, CopyError> {
+    #[allow(unused_mut)]
+    let mut total = 0;
+
+    #[cfg(target_os = "linux")]
+    {
+        let kind_in = fd_kind(fd_in);
+        let kind_out = fd_kind(fd_out);
+
+        let mut calls: Vec<CopyCall> = Vec::new();
+        if kind_in == FdKind::Regular && kind_out == FdKind::Regular {
+            calls.push(copy_file_range);
+        }
+        if kind_in == FdKind::Pipe || kind_out == FdKind::Pipe {
+            calls.push(splice);
+        }
+        if kind_in == FdKind::Regular {
+            calls.push(sendfile);
+        }
+
+        for call in calls {
+            if copy_with(call, fd_in, fd_out, &mut total)?.is_some() {
+                return Ok(total);
+            }
+        }
+    }
+
+    Ok(total + copy_buffered(fd_in, fd_out)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    fn test_data() -> Vec<u8> {
+        (0..300_000u32).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    fn scratch_file(name: &str) -> (std::path::PathBuf, File) {
+        let path = std::env::temp_dir().join(format!("plib-copy-{}-{}", std::process::id(), name));
+        let f = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        (path, f)
+    }
+
+    #[test]
+    fn test_copy_file_to_file() {
+        let data = test_data();
+        let (src_path, mut src) = scratch_file("src");
+        let (dst_path, mut dst) = scratch_file("dst");
+        src.write_all(&data).unwrap();
+        src.seek(SeekFrom::Start(10)).unwrap();
+
+        let n = copy_fd(src.as_raw_fd(), dst.as_raw_fd()).unwrap();
+        assert_eq!(n, data.len() as u64 - 10);
+
+        let mut copied = Vec::new();
+        dst.seek(SeekFrom::Start(0)).unwrap();
+        dst.read_to_end(&mut copied).unwrap();
+        assert_eq!(copied, &data[10..]);
+
+        fs::remove_file(src_path).unwrap();
+        fs::remove_file(dst_path).unwrap();
+    }
+
+    #[test]
+    fn test_copy_pipe_to_file() {
+        let data = test_data();
+        let (dst_path, mut dst) = scratch_file("pipe");
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let reader = unsafe { File::from_raw_fd(fds[0]) };
+        let mut writer = unsafe { File::from_raw_fd(fds[1]) };
+        assert!(is_pipe(reader.as_raw_fd()));
+
+        let input = data.clone();
+        let feeder = std::thread::spawn(move || writer.write_all(&input).unwrap());
+        let n = copy_fd(reader.as_raw_fd(), dst.as_raw_fd()).unwrap();
+        feeder.join().unwrap();
+        assert_eq!(n, data.len() as u64);
+
+        let mut copied = Vec::new();
+        dst.seek(SeekFrom::Start(0)).unwrap();
+        dst.read_to_end(&mut copied).unwrap();
+        assert_eq!(copied, data);
+
+        fs::remove_file(dst_path).unwrap();
+    }
+
+    #[test]
+    fn test_copy_buffered() {
+        let data = test_data();
+        let (src_path, mut src) = scratch_file("bsrc");
+        let (dst_path, dst) = scratch_file("bdst");
+        src.write_all(&data).unwrap();
+        src.seek(SeekFrom::Start(0)).unwrap();
+
+        let n = copy_buffered(src.as_raw_fd(), dst.as_raw_fd()).unwrap();
+        assert_eq!(n, data.len() as u64);
+        assert_eq!(fs::read(&dst_path).unwrap(), data);
+
+        drop(dst);
+        fs::remove_file(src_path).unwrap();
+        fs::remove_file(dst_path).unwrap();
+    }
+}
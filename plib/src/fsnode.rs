@@ -0,0 +1,105 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+// Recreating a file's node and characteristics, rather than its data,
+// when copying it: FIFOs, device nodes and sockets are made anew, and
+// the ownership, mode and times of the source are put on the copy.
+//
+
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+/// `path` as a C string, for passing to libc.
+pub fn path_cstr(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+}
+
+/// Duplicate the time of last access, time of last modification,
+/// ownership and mode of a source file onto its copy.  A symlink copy
+/// gets the ownership and times of the source symlink; it has no mode
+/// of its own.
+pub fn copy_characteristics(source_md: &fs::Metadata, target: &Path) -> io::Result<()> {
+    let target_cstr = path_cstr(target)?;
+    let is_symlink = source_md.file_type().is_symlink();
+    let mut mode = (source_md.mode() & 0o7777) as libc::mode_t;
+
+    // If ownership cannot be duplicated, the setuid and setgid bits must
+    // not be kept either.  Failure to chown is otherwise not an error.
+    let ret = unsafe {
+        if is_symlink {
+            libc::lchown(target_cstr.as_ptr(), source_md.uid(), source_md.gid())
+        } else {
+            libc::chown(target_cstr.as_ptr(), source_md.uid(), source_md.gid())
+        }
+    };
+    if ret != 0 {
+        mode &= !(libc::S_ISUID | libc::S_ISGID);
+    }
+
+    if !is_symlink {
+        let ret = unsafe { libc::chmod(target_cstr.as_ptr(), mode) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    // set times last, so that nothing above disturbs them
+    let times = [
+        libc::timespec {
+            tv_sec: source_md.atime() as libc::time_t,
+            tv_nsec: source_md.atime_nsec() as libc::c_long,
+        },
+        libc::timespec {
+            tv_sec: source_md.mtime() as libc::time_t,
+            tv_nsec: source_md.mtime_nsec() as libc::c_long,
+        },
+    ];
+    let flags = if is_symlink {
+        libc::AT_SYMLINK_NOFOLLOW
+    } else {
+        0
+    };
+    let ret =
+        unsafe { libc::utimensat(libc::AT_FDCWD, target_cstr.as_ptr(), times.as_ptr(), flags) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Create a FIFO, device node or socket at `target` of the same kind
+/// and permission bits as the source, rather than copying the data
+/// read from it.
+pub fn make_special(source_md: &fs::Metadata, target: &Path) -> io::Result<()> {
+    let target_cstr = path_cstr(target)?;
+    let perms = (source_md.mode() & 0o7777) as libc::mode_t;
+
+    let ret = unsafe {
+        if source_md.file_type().is_fifo() {
+            libc::mkfifo(target_cstr.as_ptr(), perms)
+        } else {
+            let kind = (source_md.mode() as libc::mode_t) & libc::S_IFMT;
+            libc::mknod(
+                target_cstr.as_ptr(),
+                kind | perms,
+                source_md.rdev() as libc::dev_t,
+            )
+        }
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
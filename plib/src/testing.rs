@@ -0,0 +1,76 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+// Helpers for the integration tests of each package, which run the
+// release binaries from the package's directory.
+//
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+pub struct TestPlan {
+    pub cmd: String,
+    pub args: Vec<String>,
+    pub stdin_data: String,
+    pub expected_out: String,
+    pub expected_exit_code: i32,
+}
+
+/// The path of the release binary `cmd`, relative to the workspace root.
+pub fn bin_path(cmd: &str) -> PathBuf {
+    std::env::current_dir()
+        .unwrap()
+        .parent()
+        .unwrap() // Move up to the workspace root from the current package directory
+        .join(format!("target/release/{}", cmd))
+}
+
+pub fn run_test(plan: TestPlan) {
+    let mut command = Command::new(bin_path(&plan.cmd));
+    let mut child = command
+        .args(plan.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn head");
+
+    let stdin = child.stdin.as_mut().expect("failed to get stdin");
+    stdin
+        .write_all(plan.stdin_data.as_bytes())
+        .expect("failed to write to stdin");
+
+    let output = child.wait_with_output().expect("failed to wait for child");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, plan.expected_out);
+    assert_eq!(output.status.code(), Some(plan.expected_exit_code));
+}
+
+/// A fresh, empty directory for one test, under `base`.
+pub fn temp_dir_in(base: &Path, name: &str) -> PathBuf {
+    let dir = base.join(format!("posixutils-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A fresh, empty directory for one test, under the system's temporary
+/// directory.
+pub fn temp_dir(name: &str) -> PathBuf {
+    temp_dir_in(&std::env::temp_dir(), name)
+}
+
+/// Run the release binary `cmd` in `dir`, collecting its output.
+pub fn run_in(dir: &Path, cmd: &str, args: &[&str]) -> Output {
+    Command::new(bin_path(cmd))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
@@ -6,21 +6,28 @@
 // file in the root directory of this project.
 // SPDX-License-Identifier: MIT
 //
-// TODO:
-// - Just a start with the core algorithm; -C and -U both need context output
-// - Implement -r (recurse)
-// - Research and implement -f alternate output format properly
-//
 
+extern crate chrono;
 extern crate clap;
-extern crate diff;
 extern crate plib;
 
+use chrono::{DateTime, Local};
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, textdomain};
 use plib::PROJECT_NAME;
+use std::borrow::Cow;
+use std::ffi::OsString;
 use std::fs;
-use std::io;
+use std::fs::{FileType, Metadata};
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+use std::time::SystemTime;
+
+mod myers;
+
+use myers::Change;
 
 /// diff - compare two files
 #[derive(Parser, Debug, Clone)]
@@ -35,7 +42,7 @@ struct Args {
     context3: bool,
 
     /// Output <N> lines of copied context
-    #[arg(short='C', value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(short='C', value_parser = clap::value_parser!(u32))]
     context: Option<u32>,
 
     /// Produce output in a form suitable as input for the ed utility
@@ -46,6 +53,10 @@ struct Args {
     #[arg(short)]
     fed: bool,
 
+    /// Report only whether the files differ
+    #[arg(short = 'q', long)]
+    brief: bool,
+
     /// Apply diff recursively to files and directories of the same name
     #[arg(short, long)]
     recurse: bool,
@@ -55,7 +66,7 @@ struct Args {
     unified3: bool,
 
     /// Output <N> lines of unified context
-    #[arg(short='U', value_parser = clap::value_parser!(u32).range(1..))]
+    #[arg(short='U', value_parser = clap::value_parser!(u32))]
     unified: Option<u32>,
 
     /// First comparison file (or directory, if -r is specified)
@@ -65,105 +76,695 @@ struct Args {
     file2: String,
 }
 
+#[derive(Clone, Copy)]
 enum OutputFormat {
+    Normal,
     Ed,
     Fed,
     Context(u32),
     Unified(u32),
 }
 
-fn diff_file_fed(left: &str, right: &str) {
-    let mut left_line = 0;
-    let mut right_line = 0;
+/// Outcome of a comparison, in exit status order: when several files
+/// are compared, the worst outcome determines the exit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum DiffStatus {
+    Same = 0,
+    Different = 1,
+    Trouble = 2,
+}
 
-    for diff in diff::lines(&left, &right) {
-        match diff {
-            diff::Result::Left(l) => {
-                left_line += 1;
-                println!("{}a{}", left_line, right_line);
-                println!("> {}", l);
-            }
-            diff::Result::Both(_, _) => {
-                left_line += 1;
-                right_line += 1;
+/// A comparison operand, loaded into memory.  Both operands are held
+/// whole for the length of the comparison, together with a slice per
+/// line, so memory use grows linearly with the size of the input.
+struct FileData {
+    name: String,
+    mtime: SystemTime,
+    data: Vec<u8>,
+}
+
+impl FileData {
+    /// Read a file, or standard input if the name is "-".
+    fn open(name: &str) -> io::Result<FileData> {
+        let read = || -> io::Result<(Vec<u8>, SystemTime)> {
+            if name == "-" {
+                let mut data = Vec::new();
+                io::stdin().lock().read_to_end(&mut data)?;
+                Ok((data, SystemTime::now()))
+            } else {
+                Ok((fs::read(name)?, fs::metadata(name)?.modified()?))
             }
-            diff::Result::Right(r) => {
-                right_line += 1;
-                println!("{}c{}", left_line, right_line);
-                println!("< {}", r);
+        };
+        let (data, mtime) =
+            read().map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+
+        Ok(FileData {
+            name: String::from(name),
+            mtime,
+            data,
+        })
+    }
+
+    /// Files containing NUL bytes or invalid UTF-8 are not compared
+    /// line by line.
+    fn is_binary(&self) -> bool {
+        self.data.contains(&0) || std::str::from_utf8(&self.data).is_err()
+    }
+
+    /// Split the file into lines, each including its trailing newline
+    /// (if any), so that a missing newline at EOF counts as a difference.
+    fn lines(&self) -> Vec<&[u8]> {
+        self.data.split_inclusive(|b| *b == b'\n').collect()
+    }
+}
+
+/// The comparison key for a line under -b: runs of white space are
+/// collapsed to a single space, and white space at the end of the line
+/// (including the newline) is dropped.
+fn squeeze_space(line: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(line.len());
+    let mut in_space = false;
+
+    for &b in line {
+        if b.is_ascii_whitespace() || b == 0x0b {
+            in_space = true;
+        } else {
+            if in_space {
+                key.push(b' ');
             }
+            in_space = false;
+            key.push(b);
         }
     }
+
+    key
 }
 
-fn diff_file_ed(left: &str, right: &str) {
-    let mut left_line = 0;
-    let mut right_line = 0;
+/// The result of comparing two files: both operands split into lines,
+/// and the changes that turn the first into the second.
+struct FileDiff<'a> {
+    file1: &'a FileData,
+    file2: &'a FileData,
+    left: Vec<&'a [u8]>,
+    right: Vec<&'a [u8]>,
+    changes: Vec<Change>,
+}
 
-    for diff in diff::lines(&left, &right) {
-        match diff {
-            diff::Result::Left(l) => {
-                left_line += 1;
-                println!("{}a{}", left_line, right_line);
-                println!("> {}", l);
-            }
-            diff::Result::Both(_, _) => {
-                left_line += 1;
-                right_line += 1;
+impl<'a> FileDiff<'a> {
+    fn new(file1: &'a FileData, file2: &'a FileData, ignore_space: bool) -> FileDiff<'a> {
+        let left = file1.lines();
+        let right = file2.lines();
+
+        // lines are compared through keys, but always printed as read
+        let keys = |lines: &[&'a [u8]]| -> Vec<Cow<'a, [u8]>> {
+            lines
+                .iter()
+                .map(|line| {
+                    if ignore_space {
+                        Cow::Owned(squeeze_space(line))
+                    } else {
+                        Cow::Borrowed(*line)
+                    }
+                })
+                .collect()
+        };
+        let changes = myers::diff(&keys(&left), &keys(&right));
+
+        FileDiff {
+            file1,
+            file2,
+            left,
+            right,
+            changes,
+        }
+    }
+}
+
+/// A group of changes that are printed together, along with the
+/// surrounding lines of context.
+struct Hunk<'a> {
+    changes: &'a [Change],
+    left: Range<usize>,
+    right: Range<usize>,
+}
+
+/// Group changes into hunks with `n` lines of context.  Changes separated
+/// by no more than 2*n unchanged lines share a hunk, so that context lines
+/// are never printed twice.
+fn build_hunks(changes: &[Change], n: usize, left_len: usize) -> Vec<Hunk<'_>> {
+    let mut hunks = Vec::new();
+    let mut first = 0;
+
+    while first < changes.len() {
+        let mut last = first;
+        while last + 1 < changes.len()
+            && changes[last + 1].left.start - changes[last].left.end <= 2 * n
+        {
+            last += 1;
+        }
+
+        // lines outside of changes are common to both files, so the
+        // same amount of context applies to either side
+        let head = n.min(changes[first].left.start);
+        let tail = n.min(left_len - changes[last].left.end);
+        hunks.push(Hunk {
+            changes: &changes[first..=last],
+            left: changes[first].left.start - head..changes[last].left.end + tail,
+            right: changes[first].right.start - head..changes[last].right.end + tail,
+        });
+
+        first = last + 1;
+    }
+
+    hunks
+}
+
+/// Format a hunk range as "start,count" for unified output.  An empty
+/// range is reported as the line preceding it.
+fn unified_range(range: &Range<usize>) -> String {
+    match range.len() {
+        0 => format!("{},0", range.start),
+        1 => format!("{}", range.start + 1),
+        len => format!("{},{}", range.start + 1, len),
+    }
+}
+
+/// Print a line with the given prefix, noting a missing newline at EOF.
+fn print_line(out: &mut impl Write, prefix: &str, line: &[u8]) -> io::Result<()> {
+    out.write_all(prefix.as_bytes())?;
+    out.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        out.write_all(b"\n\\ No newline at end of file\n")?;
+    }
+
+    Ok(())
+}
+
+fn unified_timestamp(mtime: SystemTime) -> String {
+    let dt: DateTime<Local> = mtime.into();
+    dt.format("%Y-%m-%d %H:%M:%S%.9f %z").to_string()
+}
+
+/// Format a range as "first,last" for normal and ed output, or as a
+/// single line number when the range covers one line.  An empty range
+/// is reported as the line preceding it.
+fn line_range(range: &Range<usize>, sep: char) -> String {
+    match range.len() {
+        0 => format!("{}", range.start),
+        1 => format!("{}", range.start + 1),
+        _ => format!("{}{}{}", range.start + 1, sep, range.end),
+    }
+}
+
+/// The ed command letter describing a change.
+fn change_command(change: &Change) -> char {
+    if change.left.is_empty() {
+        'a'
+    } else if change.right.is_empty() {
+        'd'
+    } else {
+        'c'
+    }
+}
+
+/// Print a line for ed-style output, which has no way to represent a
+/// missing newline at EOF.
+fn print_ed_line(out: &mut impl Write, prefix: &str, line: &[u8]) -> io::Result<()> {
+    out.write_all(prefix.as_bytes())?;
+    out.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        out.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn diff_file_normal(out: &mut impl Write, diff: &FileDiff) -> io::Result<()> {
+    let FileDiff {
+        left,
+        right,
+        changes,
+        ..
+    } = diff;
+
+    for change in changes {
+        writeln!(
+            out,
+            "{}{}{}",
+            line_range(&change.left, ','),
+            change_command(change),
+            line_range(&change.right, ',')
+        )?;
+
+        for line in &left[change.left.clone()] {
+            print_line(out, "< ", line)?;
+        }
+        if !change.left.is_empty() && !change.right.is_empty() {
+            writeln!(out, "---")?;
+        }
+        for line in &right[change.right.clone()] {
+            print_line(out, "> ", line)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print an ed script that converts file1 into file2.  Changes are
+/// listed last to first, so that applying each one does not disturb the
+/// line numbers of those still to come.
+fn diff_file_ed(out: &mut impl Write, diff: &FileDiff) -> io::Result<()> {
+    let FileDiff { right, changes, .. } = diff;
+
+    for change in changes.iter().rev() {
+        let cmd = change_command(change);
+        if cmd == 'a' {
+            writeln!(out, "{}a", change.left.start)?;
+        } else {
+            writeln!(out, "{}{}", line_range(&change.left, ','), cmd)?;
+        }
+        if cmd == 'd' {
+            continue;
+        }
+
+        // A line consisting of a single '.' would end input mode.  Write
+        // it as "..", leave input mode, strip the extra dot, and resume
+        // appending after it.
+        let mut insert_mode = true;
+        for line in &right[change.right.clone()] {
+            if !insert_mode {
+                writeln!(out, "a")?;
+                insert_mode = true;
             }
-            diff::Result::Right(r) => {
-                right_line += 1;
-                println!("{}c{}", left_line, right_line);
-                println!("< {}", r);
+            if *line == b".\n" || *line == b"." {
+                writeln!(out, "..\n.\ns/.//")?;
+                insert_mode = false;
+            } else {
+                print_ed_line(out, "", line)?;
             }
         }
+        if insert_mode {
+            writeln!(out, ".")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the -f format: the same edits as -e, in forward order, with the
+/// command letter preceding a space-separated range.
+fn diff_file_fed(out: &mut impl Write, diff: &FileDiff) -> io::Result<()> {
+    let FileDiff { right, changes, .. } = diff;
+
+    for change in changes {
+        let cmd = change_command(change);
+        if cmd == 'a' {
+            writeln!(out, "a{}", change.left.start)?;
+        } else {
+            writeln!(out, "{}{}", cmd, line_range(&change.left, ' '))?;
+        }
+        if cmd == 'd' {
+            continue;
+        }
+
+        for line in &right[change.right.clone()] {
+            print_ed_line(out, "", line)?;
+        }
+        writeln!(out, ".")?;
+    }
+
+    Ok(())
+}
+
+fn context_timestamp(mtime: SystemTime) -> String {
+    let dt: DateTime<Local> = mtime.into();
+    dt.format("%a %b %e %T %Y").to_string()
+}
+
+/// Print one side of a context-format hunk.  Changed lines are marked
+/// with '!' when the change has lines on both sides, or with `marker`
+/// ('-' or '+') when it only has lines on this side.
+fn print_context_side(
+    out: &mut impl Write,
+    lines: &[&[u8]],
+    range: &Range<usize>,
+    changes: &[(Range<usize>, bool)],
+    marker: &str,
+) -> io::Result<()> {
+    let mut pos = range.start;
+    for (change, both) in changes {
+        for line in &lines[pos..change.start] {
+            print_line(out, "  ", line)?;
+        }
+        let prefix = if *both { "! " } else { marker };
+        for line in &lines[change.clone()] {
+            print_line(out, prefix, line)?;
+        }
+        pos = change.end;
+    }
+    for line in &lines[pos..range.end] {
+        print_line(out, "  ", line)?;
     }
+
+    Ok(())
 }
 
-fn diff_file_context(left: &str, right: &str, _n: u32) {
-    for diff in diff::lines(&left, &right) {
-        match diff {
-            diff::Result::Left(l) => {
-                println!("< {}", l);
+fn diff_file_context(out: &mut impl Write, diff: &FileDiff, n: u32) -> io::Result<()> {
+    let FileDiff {
+        file1,
+        file2,
+        left,
+        right,
+        changes,
+    } = diff;
+
+    writeln!(out, "*** {} {}", file1.name, context_timestamp(file1.mtime))?;
+    writeln!(out, "--- {} {}", file2.name, context_timestamp(file2.mtime))?;
+
+    for hunk in build_hunks(changes, n as usize, left.len()) {
+        writeln!(out, "***************")?;
+
+        let left_changes: Vec<(Range<usize>, bool)> = hunk
+            .changes
+            .iter()
+            .map(|c| (c.left.clone(), !c.right.is_empty()))
+            .collect();
+        let right_changes: Vec<(Range<usize>, bool)> = hunk
+            .changes
+            .iter()
+            .map(|c| (c.right.clone(), !c.left.is_empty()))
+            .collect();
+
+        // a side's lines are omitted when none of the changes touch it
+        writeln!(out, "*** {} ****", line_range(&hunk.left, ','))?;
+        if hunk.changes.iter().any(|c| !c.left.is_empty()) {
+            print_context_side(out, left, &hunk.left, &left_changes, "- ")?;
+        }
+
+        writeln!(out, "--- {} ----", line_range(&hunk.right, ','))?;
+        if hunk.changes.iter().any(|c| !c.right.is_empty()) {
+            print_context_side(out, right, &hunk.right, &right_changes, "+ ")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn diff_file_unified(out: &mut impl Write, diff: &FileDiff, n: u32) -> io::Result<()> {
+    let FileDiff {
+        file1,
+        file2,
+        left,
+        right,
+        changes,
+    } = diff;
+
+    writeln!(
+        out,
+        "--- {}\t{}",
+        file1.name,
+        unified_timestamp(file1.mtime)
+    )?;
+    writeln!(
+        out,
+        "+++ {}\t{}",
+        file2.name,
+        unified_timestamp(file2.mtime)
+    )?;
+
+    for hunk in build_hunks(changes, n as usize, left.len()) {
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            unified_range(&hunk.left),
+            unified_range(&hunk.right)
+        )?;
+
+        let mut pos = hunk.left.start;
+        for change in hunk.changes {
+            for line in &left[pos..change.left.start] {
+                print_line(out, " ", line)?;
             }
-            diff::Result::Both(l, _) => {
-                println!("  {}", l);
+            for line in &left[change.left.clone()] {
+                print_line(out, "-", line)?;
             }
-            diff::Result::Right(r) => {
-                println!("> {}", r);
+            for line in &right[change.right.clone()] {
+                print_line(out, "+", line)?;
             }
+            pos = change.left.end;
+        }
+        for line in &left[pos..hunk.left.end] {
+            print_line(out, " ", line)?;
         }
     }
+
+    Ok(())
 }
 
-fn diff_file_unified(left: &str, right: &str, _n: u32) {
-    for diff in diff::lines(&left, &right) {
-        match diff {
-            diff::Result::Left(l) => {
-                println!("-{}", l);
-            }
-            diff::Result::Both(l, _) => {
-                println!(" {}", l);
-            }
-            diff::Result::Right(r) => {
-                println!("+{}", r);
+/// Settings shared by every comparison made during one run.
+struct DiffOptions {
+    out_fmt: OutputFormat,
+    brief: bool,
+    ignore_space: bool,
+    recurse: bool,
+}
+
+impl DiffOptions {
+    /// The options as they are echoed in the "diff ..." line that
+    /// introduces each pair of differing files in a directory comparison.
+    fn option_string(&self) -> String {
+        let mut opts = String::new();
+        match self.out_fmt {
+            OutputFormat::Normal => {}
+            OutputFormat::Ed => opts.push_str(" -e"),
+            OutputFormat::Fed => opts.push_str(" -f"),
+            OutputFormat::Context(n) => opts.push_str(&format!(" -C {}", n)),
+            OutputFormat::Unified(n) => opts.push_str(&format!(" -U {}", n)),
+        }
+        if self.ignore_space {
+            opts.push_str(" -b");
+        }
+        if self.recurse {
+            opts.push_str(" -r");
+        }
+
+        opts
+    }
+}
+
+/// Compare two regular files.  When `in_dir` is set, the files were
+/// found while comparing directories, and output for a differing pair
+/// is preceded by a line naming both files.
+fn diff_file(opts: &DiffOptions, path1: &str, path2: &str, in_dir: bool) -> io::Result<DiffStatus> {
+    let file1 = FileData::open(path1)?;
+    let file2 = FileData::open(path2)?;
+
+    if file1.is_binary() || file2.is_binary() {
+        if file1.data == file2.data {
+            return Ok(DiffStatus::Same);
+        }
+        if opts.brief {
+            println!("Files {} and {} differ", path1, path2);
+        } else {
+            println!("Binary files {} and {} differ", path1, path2);
+        }
+        return Ok(DiffStatus::Different);
+    }
+
+    let diff = FileDiff::new(&file1, &file2, opts.ignore_space);
+    if diff.changes.is_empty() {
+        return Ok(DiffStatus::Same);
+    }
+
+    let mut out = io::stdout().lock();
+
+    if opts.brief {
+        writeln!(out, "Files {} and {} differ", path1, path2)?;
+        return Ok(DiffStatus::Different);
+    }
+
+    if in_dir {
+        writeln!(out, "diff{} {} {}", opts.option_string(), path1, path2)?;
+    }
+
+    match opts.out_fmt {
+        OutputFormat::Normal => diff_file_normal(&mut out, &diff)?,
+        OutputFormat::Ed => diff_file_ed(&mut out, &diff)?,
+        OutputFormat::Fed => diff_file_fed(&mut out, &diff)?,
+        OutputFormat::Context(n) => diff_file_context(&mut out, &diff, n)?,
+        OutputFormat::Unified(n) => diff_file_unified(&mut out, &diff, n)?,
+    }
+
+    Ok(DiffStatus::Different)
+}
+
+/// Describe a file type the way POSIX words it in diff's
+/// "File %s is a %s while file %s is a %s" message.
+fn file_type_name(ft: FileType) -> &'static str {
+    if ft.is_dir() {
+        "directory"
+    } else if ft.is_file() {
+        "regular file"
+    } else if ft.is_fifo() {
+        "FIFO"
+    } else if ft.is_char_device() {
+        "character special file"
+    } else if ft.is_block_device() {
+        "block special file"
+    } else if ft.is_socket() {
+        "socket"
+    } else {
+        "special file"
+    }
+}
+
+/// Read the names in a directory, sorted bytewise.
+fn sorted_entries(dir: &str) -> io::Result<Vec<OsString>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        names.push(entry?.file_name());
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+fn join_path(dir: &str, name: &OsString) -> String {
+    Path::new(dir).join(name).to_string_lossy().into_owned()
+}
+
+/// Compare the entries of two directories.  `ancestors` holds the
+/// device and inode of every directory on the path being compared on
+/// the left side, so that symlink loops are not followed forever.
+///
+/// Errors on individual entries are reported and the walk continues.
+fn diff_dirs(
+    opts: &DiffOptions,
+    dir1: &str,
+    dir2: &str,
+    ancestors: &mut Vec<(u64, u64)>,
+) -> io::Result<DiffStatus> {
+    let names1 = sorted_entries(dir1)?;
+    let names2 = sorted_entries(dir2)?;
+    let mut status = DiffStatus::Same;
+
+    // walk both sorted lists in step, like a merge
+    let mut i1 = 0;
+    let mut i2 = 0;
+    while i1 < names1.len() || i2 < names2.len() {
+        if i2 == names2.len() || (i1 < names1.len() && names1[i1] < names2[i2]) {
+            println!("Only in {}: {}", dir1, names1[i1].to_string_lossy());
+            status = status.max(DiffStatus::Different);
+            i1 += 1;
+            continue;
+        }
+        if i1 == names1.len() || names2[i2] < names1[i1] {
+            println!("Only in {}: {}", dir2, names2[i2].to_string_lossy());
+            status = status.max(DiffStatus::Different);
+            i2 += 1;
+            continue;
+        }
+
+        let path1 = join_path(dir1, &names1[i1]);
+        let path2 = join_path(dir2, &names2[i2]);
+        i1 += 1;
+        i2 += 1;
+
+        match diff_dir_entry(opts, &path1, &path2, ancestors) {
+            Ok(entry_status) => status = status.max(entry_status),
+            Err(e) => {
+                eprintln!("diff: {}", e);
+                status = DiffStatus::Trouble;
             }
         }
     }
+
+    Ok(status)
 }
 
-fn diff_files(out_fmt: OutputFormat, filename1: &str, filename2: &str) -> io::Result<()> {
-    let left = fs::read_to_string(filename1)?;
-    let right = fs::read_to_string(filename2)?;
+/// Compare two same-named entries found while comparing directories.
+fn diff_dir_entry(
+    opts: &DiffOptions,
+    path1: &str,
+    path2: &str,
+    ancestors: &mut Vec<(u64, u64)>,
+) -> io::Result<DiffStatus> {
+    let meta1 = stat_path(path1)?;
+    let meta2 = stat_path(path2)?;
+    let ft1 = meta1.file_type();
+    let ft2 = meta2.file_type();
 
-    match out_fmt {
-        OutputFormat::Ed => diff_file_ed(&left, &right),
-        OutputFormat::Fed => diff_file_fed(&left, &right),
-        OutputFormat::Context(n) => diff_file_context(&left, &right, n),
-        OutputFormat::Unified(n) => diff_file_unified(&left, &right, n),
+    if ft1.is_dir() && ft2.is_dir() {
+        if !opts.recurse {
+            println!("Common subdirectories: {} and {}", path1, path2);
+            return Ok(DiffStatus::Same);
+        }
+
+        let id = (meta1.dev(), meta1.ino());
+        if ancestors.contains(&id) {
+            eprintln!("diff: {}: recursive directory loop", path1);
+            return Ok(DiffStatus::Trouble);
+        }
+
+        ancestors.push(id);
+        let res = diff_dirs(opts, path1, path2, ancestors);
+        ancestors.pop();
+        res
+    } else if ft1.is_file() && ft2.is_file() {
+        diff_file(opts, path1, path2, true)
+    } else if file_type_name(ft1) != file_type_name(ft2) {
+        println!(
+            "File {} is a {} while file {} is a {}",
+            path1,
+            file_type_name(ft1),
+            path2,
+            file_type_name(ft2)
+        );
+        Ok(DiffStatus::Different)
+    } else {
+        // special files of the same type have no contents to compare
+        Ok(DiffStatus::Same)
     }
+}
 
-    Ok(())
+/// stat(2) a path, naming it in any error.
+fn stat_path(path: &str) -> io::Result<Metadata> {
+    fs::metadata(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))
+}
+
+/// Standard input ("-") is never treated as a directory.
+fn is_dir_operand(path: &str) -> io::Result<Option<Metadata>> {
+    if path == "-" {
+        return Ok(None);
+    }
+
+    let meta = stat_path(path)?;
+    Ok(if meta.is_dir() { Some(meta) } else { None })
+}
+
+fn diff_files(opts: &DiffOptions, path1: &str, path2: &str) -> io::Result<DiffStatus> {
+    let dir1 = is_dir_operand(path1)?;
+    let dir2 = is_dir_operand(path2)?;
+
+    match (dir1, dir2) {
+        (Some(meta1), Some(_)) => {
+            let mut ancestors = vec![(meta1.dev(), meta1.ino())];
+            diff_dirs(opts, path1, path2, &mut ancestors)
+        }
+
+        // a file compared against a directory is compared against the
+        // file of the same name inside that directory
+        (Some(_), None) => {
+            let name = Path::new(path2).file_name().unwrap_or_default();
+            diff_file(opts, &join_path(path1, &name.into()), path2, false)
+        }
+        (None, Some(_)) => {
+            let name = Path::new(path1).file_name().unwrap_or_default();
+            diff_file(opts, path1, &join_path(path2, &name.into()), false)
+        }
+
+        (None, None) => diff_file(opts, path1, path2, false),
+    }
 }
 
 fn parse_output_format(args: &Args) -> OutputFormat {
@@ -185,7 +786,7 @@ fn parse_output_format(args: &Args) -> OutputFormat {
     } else if let Some(n) = args.unified {
         OutputFormat::Unified(n)
     } else {
-        OutputFormat::Unified(3)
+        OutputFormat::Normal
     }
 }
 
@@ -196,14 +797,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
-    let mut exit_code = 0;
-
-    let out_fmt = parse_output_format(&args);
+    let opts = DiffOptions {
+        out_fmt: parse_output_format(&args),
+        brief: args.brief,
+        ignore_space: args.ignore_eol_space,
+        recurse: args.recurse,
+    };
 
-    if let Err(e) = diff_files(out_fmt, &args.file1, &args.file2) {
-        exit_code = 1;
-        eprintln!("diff: {}", e);
-    }
+    let status = match diff_files(&opts, &args.file1, &args.file2) {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("diff: {}", e);
+            DiffStatus::Trouble
+        }
+    };
 
-    std::process::exit(exit_code)
+    std::process::exit(status as i32)
 }
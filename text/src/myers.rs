@@ -0,0 +1,476 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+// Line diff engine: Myers' O(ND) algorithm in linear space ("An O(ND)
+// Difference Algorithm and Its Variations", Algorithmica 1986), with the
+// preprocessing and cost cutoff heuristic used by most diff implementations.
+// "Linear space" is the working space of the search; the lines themselves
+// are supplied by the caller, so overall memory grows with the input size.
+//
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+/// A run of lines in the left file that was replaced by a run of lines
+/// in the right file.  Ranges are zero-based and half-open; either one
+/// may be empty (pure insertion or pure deletion).
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub left: Range<usize>,
+    pub right: Range<usize>,
+}
+
+/// Compute the changes that turn `left` into `right`.
+pub fn diff<T: Hash + Eq>(left: &[T], right: &[T]) -> Vec<Change> {
+    // Replace each line with a small integer, so that the core algorithm
+    // compares integers rather than line contents.
+    let mut ids: HashMap<&T, u32> = HashMap::new();
+    let mut intern = |line| {
+        let next = ids.len() as u32;
+        *ids.entry(line).or_insert(next)
+    };
+    let a: Vec<u32> = left.iter().map(&mut intern).collect();
+    let b: Vec<u32> = right.iter().map(&mut intern).collect();
+
+    let mut changed_a = vec![false; a.len()];
+    let mut changed_b = vec![false; b.len()];
+
+    // Lines common to the start and end of both files never need to
+    // reach the core algorithm.
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_mid = prefix..a.len() - suffix;
+    let b_mid = prefix..b.len() - suffix;
+
+    // A line that appears nowhere in the other file cannot be part of
+    // any common subsequence.  Mark such lines changed up front and run
+    // the algorithm on what is left; for unrelated files this shrinks
+    // the problem dramatically.
+    let mut in_a = vec![false; ids.len()];
+    let mut in_b = vec![false; ids.len()];
+    for &id in &a[a_mid.clone()] {
+        in_a[id as usize] = true;
+    }
+    for &id in &b[b_mid.clone()] {
+        in_b[id as usize] = true;
+    }
+    let (a_kept, a_index) = keep_matching(&a, a_mid, &in_b, &mut changed_a);
+    let (b_kept, b_index) = keep_matching(&b, b_mid, &in_a, &mut changed_b);
+
+    let mut ctx = Context::new(&a_kept, &b_kept);
+    ctx.compare_seq(0, a_kept.len(), 0, b_kept.len());
+
+    // map results for the kept lines back to their original positions
+    for (i, &changed) in ctx.changed_a.iter().enumerate() {
+        changed_a[a_index[i]] = changed;
+    }
+    for (i, &changed) in ctx.changed_b.iter().enumerate() {
+        changed_b[b_index[i]] = changed;
+    }
+
+    build_script(&changed_a, &changed_b)
+}
+
+/// Return the lines of `seq[range]` that also appear in the other file,
+/// along with their original indices; mark the others changed.
+fn keep_matching(
+    seq: &[u32],
+    range: Range<usize>,
+    in_other: &[bool],
+    changed: &mut [bool],
+) -> (Vec<u32>, Vec<usize>) {
+    let mut kept = Vec::with_capacity(range.len());
+    let mut index = Vec::with_capacity(range.len());
+
+    for i in range {
+        if in_other[seq[i] as usize] {
+            kept.push(seq[i]);
+            index.push(i);
+        } else {
+            changed[i] = true;
+        }
+    }
+
+    (kept, index)
+}
+
+/// Pair up unchanged lines in order, and collect the changed lines
+/// between them into runs.
+fn build_script(changed_a: &[bool], changed_b: &[bool]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut i = 0;
+    let mut j = 0;
+
+    while i < changed_a.len() || j < changed_b.len() {
+        if i < changed_a.len() && j < changed_b.len() && !changed_a[i] && !changed_b[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let (start_i, start_j) = (i, j);
+        while i < changed_a.len() && changed_a[i] {
+            i += 1;
+        }
+        while j < changed_b.len() && changed_b[j] {
+            j += 1;
+        }
+        changes.push(Change {
+            left: start_i..i,
+            right: start_j..j,
+        });
+    }
+
+    changes
+}
+
+/// State for one run of the divide-and-conquer algorithm.
+struct Context<'a> {
+    a: &'a [u32],
+    b: &'a [u32],
+    changed_a: Vec<bool>,
+    changed_b: Vec<bool>,
+
+    // Furthest-reaching x on each diagonal k = x - y, for the forward
+    // and backward searches, indexed by k + `offset`.
+    fd: Vec<isize>,
+    bd: Vec<isize>,
+    offset: isize,
+
+    // Edit cost beyond which an approximate midpoint is accepted.
+    too_expensive: isize,
+}
+
+impl<'a> Context<'a> {
+    fn new(a: &'a [u32], b: &'a [u32]) -> Context<'a> {
+        let diags = a.len() + b.len() + 3;
+
+        // roughly the square root of the number of diagonals, but never
+        // so small that ordinary files are diffed approximately
+        let mut too_expensive: isize = 1;
+        let mut n = diags;
+        while n != 0 {
+            too_expensive <<= 1;
+            n >>= 2;
+        }
+
+        Context {
+            a,
+            b,
+            changed_a: vec![false; a.len()],
+            changed_b: vec![false; b.len()],
+            fd: vec![0; diags],
+            bd: vec![0; diags],
+            offset: b.len() as isize + 1,
+            too_expensive: too_expensive.max(4096),
+        }
+    }
+
+    /// Compare a[xoff..xlim] with b[yoff..ylim], marking changed lines.
+    fn compare_seq(&mut self, mut xoff: usize, mut xlim: usize, mut yoff: usize, mut ylim: usize) {
+        // slide over any common prefix and suffix of the two ranges
+        while xoff < xlim && yoff < ylim && self.a[xoff] == self.b[yoff] {
+            xoff += 1;
+            yoff += 1;
+        }
+        while xlim > xoff && ylim > yoff && self.a[xlim - 1] == self.b[ylim - 1] {
+            xlim -= 1;
+            ylim -= 1;
+        }
+
+        if xoff == xlim {
+            self.changed_b[yoff..ylim].fill(true);
+        } else if yoff == ylim {
+            self.changed_a[xoff..xlim].fill(true);
+        } else {
+            let (xmid, ymid) = self.find_split(xoff, xlim, yoff, ylim);
+            self.compare_seq(xoff, xmid, yoff, ymid);
+            self.compare_seq(xmid, xlim, ymid, ylim);
+        }
+    }
+
+    /// Find the midpoint of a shortest edit script for
+    /// a[xoff..xlim] vs b[yoff..ylim], by running the search forward
+    /// from the start and backward from the end until the two meet.
+    /// The ranges must be non-empty and differ in their first and last
+    /// elements.
+    fn find_split(&mut self, xoff: usize, xlim: usize, yoff: usize, ylim: usize) -> (usize, usize) {
+        let (a, b) = (self.a, self.b);
+        let off = self.offset;
+        let (xoff, xlim, yoff, ylim) = (xoff as isize, xlim as isize, yoff as isize, ylim as isize);
+
+        let dmin = xoff - ylim;
+        let dmax = xlim - yoff;
+        let fmid = xoff - yoff;
+        let bmid = xlim - ylim;
+        let odd = (fmid - bmid) & 1 != 0;
+
+        let (mut fmin, mut fmax) = (fmid, fmid);
+        let (mut bmin, mut bmax) = (bmid, bmid);
+        self.fd[(fmid + off) as usize] = xoff;
+        self.bd[(bmid + off) as usize] = xlim;
+
+        let mut cost = 1;
+        loop {
+            // extend the forward search by one edit
+            if fmin > dmin {
+                fmin -= 1;
+                self.fd[(fmin - 1 + off) as usize] = -1;
+            } else {
+                fmin += 1;
+            }
+            if fmax < dmax {
+                fmax += 1;
+                self.fd[(fmax + 1 + off) as usize] = -1;
+            } else {
+                fmax -= 1;
+            }
+
+            let mut d = fmax;
+            while d >= fmin {
+                let tlo = self.fd[(d - 1 + off) as usize];
+                let thi = self.fd[(d + 1 + off) as usize];
+                let mut x = if tlo >= thi { tlo + 1 } else { thi };
+                let mut y = x - d;
+                while x < xlim && y < ylim && a[x as usize] == b[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                self.fd[(d + off) as usize] = x;
+                if odd && bmin <= d && d <= bmax && self.bd[(d + off) as usize] <= x {
+                    return (x as usize, y as usize);
+                }
+                d -= 2;
+            }
+
+            // extend the backward search by one edit
+            if bmin > dmin {
+                bmin -= 1;
+                self.bd[(bmin - 1 + off) as usize] = isize::MAX;
+            } else {
+                bmin += 1;
+            }
+            if bmax < dmax {
+                bmax += 1;
+                self.bd[(bmax + 1 + off) as usize] = isize::MAX;
+            } else {
+                bmax -= 1;
+            }
+
+            let mut d = bmax;
+            while d >= bmin {
+                let tlo = self.bd[(d - 1 + off) as usize];
+                let thi = self.bd[(d + 1 + off) as usize];
+                let mut x = if tlo < thi { tlo } else { thi - 1 };
+                let mut y = x - d;
+                while x > xoff && y > yoff && a[(x - 1) as usize] == b[(y - 1) as usize] {
+                    x -= 1;
+                    y -= 1;
+                }
+                self.bd[(d + off) as usize] = x;
+                if !odd && fmin <= d && d <= fmax && x <= self.fd[(d + off) as usize] {
+                    return (x as usize, y as usize);
+                }
+                d -= 2;
+            }
+
+            // The searches have not met and the edit script is already
+            // long.  Give up on a minimal result, and split at whichever
+            // search has made the most progress.
+            if cost >= self.too_expensive {
+                let mut fxybest = -1;
+                let mut fxbest = 0;
+                let mut d = fmax;
+                while d >= fmin {
+                    let mut x = self.fd[(d + off) as usize].min(xlim);
+                    let mut y = x - d;
+                    if ylim < y {
+                        x = ylim + d;
+                        y = ylim;
+                    }
+                    if fxybest < x + y {
+                        fxybest = x + y;
+                        fxbest = x;
+                    }
+                    d -= 2;
+                }
+
+                let mut bxybest = isize::MAX;
+                let mut bxbest = 0;
+                let mut d = bmax;
+                while d >= bmin {
+                    let mut x = self.bd[(d + off) as usize].max(xoff);
+                    let mut y = x - d;
+                    if y < yoff {
+                        x = yoff + d;
+                        y = yoff;
+                    }
+                    if x + y < bxybest {
+                        bxybest = x + y;
+                        bxbest = x;
+                    }
+                    d -= 2;
+                }
+
+                return if (xlim + ylim) - bxybest < fxybest - (xoff + yoff) {
+                    (fxbest as usize, (fxybest - fxbest) as usize)
+                } else {
+                    (bxbest as usize, (bxybest - bxbest) as usize)
+                };
+            }
+
+            cost += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data(len: usize, alphabet: u32, seed: u32) -> Vec<u32> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x % alphabet
+            })
+            .collect()
+    }
+
+    /// Apply `changes` to `left`, checking along the way that they are
+    /// in order and that the lines between them match on both sides.
+    fn apply<T: Clone + PartialEq + std::fmt::Debug>(
+        left: &[T],
+        right: &[T],
+        changes: &[Change],
+    ) -> Vec<T> {
+        let mut out = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        for change in changes {
+            assert!(i <= change.left.start && j <= change.right.start);
+            assert!(!change.left.is_empty() || !change.right.is_empty());
+            assert_eq!(change.left.start - i, change.right.start - j);
+            assert_eq!(left[i..change.left.start], right[j..change.right.start]);
+            out.extend_from_slice(&left[i..change.left.start]);
+            out.extend_from_slice(&right[change.right.clone()]);
+            i = change.left.end;
+            j = change.right.end;
+        }
+        assert_eq!(left[i..], right[j..]);
+        out.extend_from_slice(&left[i..]);
+        out
+    }
+
+    /// The number of lines deleted and inserted.
+    fn cost(changes: &[Change]) -> usize {
+        changes.iter().map(|c| c.left.len() + c.right.len()).sum()
+    }
+
+    /// The least possible cost, from the longest common subsequence.
+    fn min_cost(left: &[u32], right: &[u32]) -> usize {
+        let mut lcs = vec![vec![0; right.len() + 1]; left.len() + 1];
+        for i in 0..left.len() {
+            for j in 0..right.len() {
+                lcs[i + 1][j + 1] = if left[i] == right[j] {
+                    lcs[i][j] + 1
+                } else {
+                    lcs[i][j + 1].max(lcs[i + 1][j])
+                };
+            }
+        }
+        left.len() + right.len() - 2 * lcs[left.len()][right.len()]
+    }
+
+    #[test]
+    fn test_empty_and_identical() {
+        let empty: [&str; 0] = [];
+        let lines = ["a", "b", "c"];
+
+        assert!(diff(&empty, &empty).is_empty());
+        assert!(diff(&lines, &lines).is_empty());
+
+        let changes = diff(&empty, &lines);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            (changes[0].left.clone(), changes[0].right.clone()),
+            (0..0, 0..3)
+        );
+
+        let changes = diff(&lines, &empty);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            (changes[0].left.clone(), changes[0].right.clone()),
+            (0..3, 0..0)
+        );
+    }
+
+    #[test]
+    fn test_known_script() {
+        let left = ["one", "two", "three", "four", "five"];
+        let right = ["one", "2", "three", "four", ".", "five", "six"];
+        let changes = diff(&left, &right);
+
+        let ranges: Vec<_> = changes
+            .iter()
+            .map(|c| (c.left.clone(), c.right.clone()))
+            .collect();
+        assert_eq!(ranges, [(1..2, 1..2), (4..4, 4..5), (5..5, 6..7)]);
+        assert_eq!(apply(&left, &right, &changes), right);
+    }
+
+    #[test]
+    fn test_minimal_small() {
+        for seed in 1..500 {
+            let left = test_data(seed as usize % 13, 4, seed);
+            let right = test_data(seed as usize % 11, 4, seed * 7919);
+            let changes = diff(&left, &right);
+
+            assert_eq!(apply(&left, &right, &changes), right);
+            assert_eq!(cost(&changes), min_cost(&left, &right), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_large() {
+        // Lines found in only one file are discarded before the search;
+        // the rest differ so much that the search gives up on a minimal
+        // script and splits approximately.
+        let mut left = test_data(12_000, 64, 1);
+        let mut right = test_data(12_000, 64, 2);
+        for i in (0..left.len()).step_by(10) {
+            left[i] = 1000 + i as u32;
+            right[i] = 100_000 + i as u32;
+        }
+
+        let changes = diff(&left, &right);
+        assert_eq!(apply(&left, &right, &changes), right);
+    }
+
+    #[test]
+    fn test_approximate_split() {
+        // with a tiny cost limit, every split is approximate
+        let left = test_data(300, 8, 3);
+        let right = test_data(300, 8, 4);
+        let mut ctx = Context::new(&left, &right);
+        ctx.too_expensive = 1;
+        ctx.compare_seq(0, left.len(), 0, right.len());
+
+        let changes = build_script(&ctx.changed_a, &ctx.changed_b);
+        assert_eq!(apply(&left, &right, &changes), right);
+        assert!(cost(&changes) >= min_cost(&left, &right));
+    }
+}
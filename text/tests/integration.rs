@@ -8,6 +8,44 @@
 //
 
 use plib::{run_test, TestPlan};
+use std::process::Command;
+
+fn diff_test(args: &[&str], test_data: &str, expected_output: &str, expected_exit_code: i32) {
+    let str_args: Vec<String> = args.iter().map(|s| String::from(*s)).collect();
+
+    run_test(TestPlan {
+        cmd: String::from("diff"),
+        args: str_args,
+        stdin_data: String::from(test_data),
+        expected_out: String::from(expected_output),
+        expected_exit_code,
+    });
+}
+
+/// Run diff, expecting context or unified output.  The timestamps in
+/// the two header lines depend on the fixtures' modification times, so
+/// only the pathnames are kept from those.
+fn diff_context_test(args: &[&str], expected_output: &str, expected_exit_code: i32) {
+    let path = std::env::current_dir()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("target/release/diff");
+    let output = Command::new(path).args(args).output().unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.split_inclusive('\n');
+    let mut actual = String::new();
+    for line in lines.by_ref().take(2) {
+        let fields: Vec<&str> = line.split_whitespace().take(2).collect();
+        actual.push_str(&fields.join(" "));
+        actual.push('\n');
+    }
+    actual.extend(lines);
+
+    assert_eq!(actual, expected_output);
+    assert_eq!(output.status.code(), Some(expected_exit_code));
+}
 
 fn expand_test_noargs(test_data: &str, expected_output: &str) {
     run_test(TestPlan {
@@ -15,6 +53,7 @@ fn expand_test_noargs(test_data: &str, expected_output: &str) {
         args: Vec::new(),
         stdin_data: String::from(test_data),
         expected_out: String::from(expected_output),
+        expected_exit_code: 0,
     });
 }
 
@@ -24,6 +63,7 @@ fn head_test(test_data: &str, expected_output: &str) {
         args: Vec::new(),
         stdin_data: String::from(test_data),
         expected_out: String::from(expected_output),
+        expected_exit_code: 0,
     });
 }
 
@@ -35,6 +75,7 @@ fn wc_test(args: &[&str], test_data: &str, expected_output: &str) {
         args: str_args,
         stdin_data: String::from(test_data),
         expected_out: String::from(expected_output),
+        expected_exit_code: 0,
     });
 }
 
@@ -77,3 +118,183 @@ fn test_wc_two() {
     wc_test(&["-l"], "x y\n", "1\n");
     wc_test(&["-w"], "x y\n", "2\n");
 }
+
+#[test]
+fn test_diff_same() {
+    diff_test(&["tests/diff/f1", "tests/diff/f1"], "", "", 0);
+    diff_test(
+        &["-", "tests/diff/f1"],
+        "one\ntwo\nthree\nfour\nfive\n",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_diff_normal() {
+    diff_test(
+        &["tests/diff/f1", "tests/diff/f2"],
+        "",
+        "2c2\n< two\n---\n> 2\n4a5\n> .\n5a7\n> six\n",
+        1,
+    );
+}
+
+#[test]
+fn test_diff_ed() {
+    diff_test(
+        &["-e", "tests/diff/f1", "tests/diff/f2"],
+        "",
+        "5a\nsix\n.\n4a\n..\n.\ns/.//\n2c\n2\n.\n",
+        1,
+    );
+    diff_test(
+        &["-f", "tests/diff/f1", "tests/diff/f2"],
+        "",
+        "c2\n2\n.\na4\n.\n.\na5\nsix\n.\n",
+        1,
+    );
+}
+
+#[test]
+fn test_diff_unified() {
+    let expected = "--- tests/diff/f1\n+++ tests/diff/f2\n\
+                    @@ -1,5 +1,7 @@\n one\n-two\n+2\n three\n four\n+.\n five\n+six\n";
+    diff_context_test(&["-u", "tests/diff/f1", "tests/diff/f2"], expected, 1);
+    diff_context_test(&["-U", "3", "tests/diff/f1", "tests/diff/f2"], expected, 1);
+    diff_context_test(&["-u", "tests/diff/f1", "tests/diff/f1"], "", 0);
+}
+
+#[test]
+fn test_diff_context() {
+    let expected = "*** tests/diff/f1\n--- tests/diff/f2\n\
+                    ***************\n*** 1,5 ****\n  one\n! two\n  three\n  four\n  five\n\
+                    --- 1,7 ----\n  one\n! 2\n  three\n  four\n+ .\n  five\n+ six\n";
+    diff_context_test(&["-c", "tests/diff/f1", "tests/diff/f2"], expected, 1);
+    diff_context_test(&["-C", "3", "tests/diff/f1", "tests/diff/f2"], expected, 1);
+    diff_context_test(&["-c", "tests/diff/f1", "tests/diff/f1"], "", 0);
+}
+
+#[test]
+fn test_diff_unified_counts() {
+    let header = "--- tests/diff/f3\n+++ tests/diff/f4\n";
+    diff_context_test(
+        &["-U", "0", "tests/diff/f3", "tests/diff/f4"],
+        &format!(
+            "{}@@ -5 +5 @@\n-5\n+five\n@@ -10 +9,0 @@\n-10\n\
+             @@ -15 +14 @@\n-15\n+fifteen\n@@ -20,0 +20 @@\n+21\n",
+            header
+        ),
+        1,
+    );
+    diff_context_test(
+        &["-U", "1", "tests/diff/f3", "tests/diff/f4"],
+        &format!(
+            "{}@@ -4,3 +4,3 @@\n 4\n-5\n+five\n 6\n@@ -9,3 +9,2 @@\n 9\n-10\n 11\n\
+             @@ -14,3 +13,3 @@\n 14\n-15\n+fifteen\n 16\n@@ -20 +19,2 @@\n 20\n+21\n",
+            header
+        ),
+        1,
+    );
+
+    // changes no more than twice the context apart share a hunk
+    diff_context_test(
+        &["-U", "5", "tests/diff/f3", "tests/diff/f4"],
+        &format!(
+            "{}@@ -1,20 +1,20 @@\n 1\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n 9\n\
+             -10\n 11\n 12\n 13\n 14\n-15\n+fifteen\n 16\n 17\n 18\n 19\n 20\n+21\n",
+            header
+        ),
+        1,
+    );
+}
+
+#[test]
+fn test_diff_context_counts() {
+    let header = "*** tests/diff/f3\n--- tests/diff/f4\n";
+    diff_context_test(
+        &["-C", "0", "tests/diff/f3", "tests/diff/f4"],
+        &format!(
+            "{}***************\n*** 5 ****\n! 5\n--- 5 ----\n! five\n\
+             ***************\n*** 10 ****\n- 10\n--- 9 ----\n\
+             ***************\n*** 15 ****\n! 15\n--- 14 ----\n! fifteen\n\
+             ***************\n*** 20 ****\n--- 20 ----\n+ 21\n",
+            header
+        ),
+        1,
+    );
+    diff_context_test(
+        &["-C", "1", "tests/diff/f3", "tests/diff/f4"],
+        &format!(
+            "{}***************\n*** 4,6 ****\n  4\n! 5\n  6\n--- 4,6 ----\n  4\n! five\n  6\n\
+             ***************\n*** 9,11 ****\n  9\n- 10\n  11\n--- 9,10 ----\n\
+             ***************\n*** 14,16 ****\n  14\n! 15\n  16\n--- 13,15 ----\n  14\n! fifteen\n  16\n\
+             ***************\n*** 20 ****\n--- 19,20 ----\n  20\n+ 21\n",
+            header
+        ),
+        1,
+    );
+}
+
+#[test]
+fn test_diff_ignore_space() {
+    diff_test(
+        &["-b", "-", "tests/diff/f1"],
+        "one\n two \nthree\nfour\nfive",
+        "2c2\n<  two \n---\n> two\n",
+        1,
+    );
+    diff_test(
+        &["-b", "-", "tests/diff/f1"],
+        "one\ntwo  \nthree\nfour\nfive",
+        "",
+        0,
+    );
+}
+
+#[test]
+fn test_diff_binary() {
+    diff_test(
+        &["-", "tests/diff/f1"],
+        "one\0",
+        "Binary files - and tests/diff/f1 differ\n",
+        1,
+    );
+    diff_test(
+        &["-q", "-", "tests/diff/f1"],
+        "one\0",
+        "Files - and tests/diff/f1 differ\n",
+        1,
+    );
+}
+
+#[test]
+fn test_diff_dirs() {
+    diff_test(
+        &["tests/diff/dir1", "tests/diff/dir2"],
+        "",
+        "diff tests/diff/dir1/b tests/diff/dir2/b\n2c2\n< two\n---\n> 2\n\
+         Only in tests/diff/dir1: only1\n\
+         Only in tests/diff/dir2: only2\n\
+         Common subdirectories: tests/diff/dir1/sub and tests/diff/dir2/sub\n\
+         File tests/diff/dir1/x is a regular file while file tests/diff/dir2/x is a directory\n",
+        1,
+    );
+
+    // with -r, common subdirectories are compared in turn
+    diff_test(
+        &["-r", "tests/diff/dir1", "tests/diff/dir2"],
+        "",
+        "diff -r tests/diff/dir1/b tests/diff/dir2/b\n2c2\n< two\n---\n> 2\n\
+         Only in tests/diff/dir1: only1\n\
+         Only in tests/diff/dir2: only2\n\
+         diff -r tests/diff/dir1/sub/c tests/diff/dir2/sub/c\n1c1\n< c\n---\n> C\n\
+         File tests/diff/dir1/x is a regular file while file tests/diff/dir2/x is a directory\n",
+        1,
+    );
+}
+
+#[test]
+fn test_diff_missing_file() {
+    diff_test(&["tests/diff/f1", "tests/diff/missing"], "", "", 2);
+}
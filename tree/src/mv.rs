@@ -7,7 +7,6 @@
 // SPDX-License-Identifier: MIT
 //
 // TODO:
-// - preserve file attributes during copy
 // - do not repeatedly stat(2) the target, for each source
 //
 
@@ -18,8 +17,12 @@ extern crate plib;
 
 use clap::Parser;
 use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
+use plib::fsnode::{copy_characteristics, make_special, path_cstr};
 use plib::PROJECT_NAME;
-use std::path::Path;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 
 /// mv - move files
@@ -27,13 +30,33 @@ use std::{fs, io};
 #[command(author, version, about, long_about)]
 struct Args {
     /// Do not prompt for confirmation if the destination path exists
-    #[arg(short, long)]
+    #[arg(short, long, overrides_with_all = ["interactive", "no_clobber"])]
     force: bool,
 
     /// Prompt for confirmation if the destination path exists.
-    #[arg(short, long)]
+    #[arg(short, long, overrides_with_all = ["force", "no_clobber"])]
     interactive: bool,
 
+    /// Never overwrite an existing destination path.
+    #[arg(short, long, overrides_with_all = ["force", "interactive"])]
+    no_clobber: bool,
+
+    /// Move only when the source is newer than the destination, or the destination is missing.
+    #[arg(short, long)]
+    update: bool,
+
+    /// Report each file as it is moved.
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Treat the target as a plain file, even if it is a directory.
+    #[arg(short = 'T', long)]
+    no_target_directory: bool,
+
+    /// Atomically exchange the source and the destination, which must both exist.
+    #[arg(long, conflicts_with_all = ["no_clobber", "update"])]
+    exchange: bool,
+
     /// Source(s) and target of move(s)
     files: Vec<String>,
 }
@@ -41,6 +64,10 @@ struct Args {
 struct Config {
     force: bool,
     interactive: bool,
+    no_clobber: bool,
+    update: bool,
+    verbose: bool,
+    exchange: bool,
     is_terminal: bool,
 }
 
@@ -49,25 +76,221 @@ impl Config {
         Config {
             force: args.force,
             interactive: args.interactive,
+            no_clobber: args.no_clobber,
+            update: args.update,
+            verbose: args.verbose,
+            exchange: args.exchange,
             is_terminal: atty::is(atty::Stream::Stdin),
         }
     }
 }
 
-fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
-    fs::create_dir_all(&dst)?;
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
-        if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
-        }
+// renameat2(2) flags
+const RENAME_NOREPLACE: u32 = 1 << 0;
+const RENAME_EXCHANGE: u32 = 1 << 1;
+
+/// rename(2) with renameat2(2) flags, which make the rename fail rather
+/// than replace an existing target, or swap the two paths.
+#[cfg(target_os = "linux")]
+fn rename_with_flags(source: &Path, target: &Path, flags: u32) -> io::Result<()> {
+    let source_cstr = path_cstr(source)?;
+    let target_cstr = path_cstr(target)?;
+
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            source_cstr.as_ptr(),
+            libc::AT_FDCWD,
+            target_cstr.as_ptr(),
+            flags,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
     }
+
     Ok(())
 }
 
+#[cfg(not(target_os = "linux"))]
+fn rename_with_flags(_source: &Path, _target: &Path, _flags: u32) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+/// `path` made absolute, with symbolic links resolved in its directory
+/// but not in its last component, which is what rename(2) acts on.
+fn resolve_parent(path: &Path) -> io::Result<PathBuf> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))?;
+
+    Ok(fs::canonicalize(parent)?.join(name))
+}
+
+/// Is one of `source` and `target` the other, or inside it?  rename(2)
+/// refuses such a pair with EINVAL, as it does a path ending in "..".
+fn is_nested(source: &Path, target: &Path) -> bool {
+    match (resolve_parent(source), resolve_parent(target)) {
+        (Ok(source), Ok(target)) => source.starts_with(&target) || target.starts_with(&source),
+        _ => true,
+    }
+}
+
+/// Did renameat2(2) fail because the kernel or filesystem lacks it?
+/// ENOSYS says so plainly.  EINVAL also means a directory moved into
+/// itself, so it counts only if `source` and `target` are not nested.
+fn is_unsupported(e: &io::Error, source: &Path, target: &Path) -> bool {
+    match e.raw_os_error() {
+        Some(libc::ENOSYS) => true,
+        Some(libc::EINVAL) => !is_nested(source, target),
+        _ => false,
+    }
+}
+
+/// Rename without replacing an existing target.  Returns Ok(false) if
+/// the target exists.  Where renameat2(2) is unavailable, this falls
+/// back to a plain rename(2), after the caller's non-atomic check.
+fn rename_noreplace(source: &Path, target: &Path) -> io::Result<bool> {
+    match rename_with_flags(source, target, RENAME_NOREPLACE) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) if is_unsupported(&e, source, target) => {
+            eprintln!(
+                "mv: {}: {}",
+                target.display(),
+                gettext("atomic no-clobber rename not supported; checked non-atomically")
+            );
+            fs::rename(source, target)?;
+            Ok(true)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Copy a file hierarchy to another filesystem, as it would look had it
+/// been renamed: symlinks are never followed, FIFOs and device nodes are
+/// recreated, all characteristics are kept, and files hard-linked to one
+/// another stay linked.  `links` maps the device and inode of each
+/// multiply-linked file copied so far to the path of its copy.
+fn copy_hierarchy(
+    src: &Path,
+    dst: &Path,
+    src_md: &fs::Metadata,
+    links: &mut HashMap<(u64, u64), PathBuf>,
+) -> io::Result<()> {
+    let ft = src_md.file_type();
+
+    if !ft.is_dir() && src_md.nlink() > 1 {
+        let id = (src_md.dev(), src_md.ino());
+        if let Some(first) = links.get(&id) {
+            return fs::hard_link(first, dst);
+        }
+        links.insert(id, dst.to_path_buf());
+    }
+
+    if ft.is_dir() {
+        fs::create_dir(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            let entry_md = fs::symlink_metadata(entry.path())?;
+            copy_hierarchy(
+                &entry.path(),
+                &dst.join(entry.file_name()),
+                &entry_md,
+                links,
+            )?;
+        }
+    } else if ft.is_file() {
+        fs::copy(src, dst)?;
+    } else if ft.is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(src)?, dst)?;
+    } else {
+        make_special(src_md, dst)?;
+    }
+
+    // for a directory, this comes after its entries are written, since
+    // creating them updates the directory's modification time
+    copy_characteristics(src_md, dst)
+}
+
+fn remove_hierarchy(path: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// A name in the target's directory for the copy to be built under,
+/// so that a failed copy never disturbs an existing target.
+fn staging_path(target: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(format!(".mv{}", std::process::id()));
+
+    target.with_file_name(name)
+}
+
+/// Move across filesystems: copy the source to a staging name beside the
+/// target, put it in place, and only then remove the source.  If the
+/// copy fails, the partial copy is removed and nothing else changes.
+///
+/// Returns Ok(false) if, under -n, the target turned out to exist.
+fn move_across_devices(
+    cfg: &Config,
+    source: &Path,
+    target: &Path,
+    source_md: &fs::Metadata,
+    target_md: Option<&fs::Metadata>,
+) -> io::Result<bool> {
+    let source_is_dir = source_md.is_dir();
+    let staging = staging_path(target);
+
+    let mut links = HashMap::new();
+    if let Err(e) = copy_hierarchy(source, &staging, source_md, &mut links) {
+        let _ = remove_hierarchy(&staging, source_is_dir);
+        return Err(e);
+    }
+
+    // Replace the target.  rename(2) atomically replaces a
+    // non-directory; a directory must be empty, and is removed first.
+    if target_md.is_some_and(|md| md.is_dir()) {
+        if let Err(e) = fs::remove_dir(target) {
+            let _ = remove_hierarchy(&staging, source_is_dir);
+            return Err(io::Error::new(
+                e.kind(),
+                format!("{}: {}", target.display(), e),
+            ));
+        }
+    }
+    let renamed = if cfg.no_clobber {
+        rename_noreplace(&staging, target)
+    } else {
+        fs::rename(&staging, target).map(|_| true)
+    };
+    match renamed {
+        Ok(true) => {}
+        Ok(false) => {
+            remove_hierarchy(&staging, source_is_dir)?;
+            return Ok(false);
+        }
+        Err(e) => {
+            let _ = remove_hierarchy(&staging, source_is_dir);
+            return Err(e);
+        }
+    }
+
+    // The move is complete once the source is gone.  If that fails, the
+    // copy stays in place, as the source may be partly removed already.
+    remove_hierarchy(source, source_is_dir)?;
+    Ok(true)
+}
+
 fn prompt_user(prompt: &str) -> bool {
     eprint!("{} ", prompt);
     let mut response = String::new();
@@ -75,42 +298,78 @@ fn prompt_user(prompt: &str) -> bool {
     response.to_lowercase().starts_with('y')
 }
 
-fn move_file(cfg: &Config, source: &str, target: &str) -> io::Result<()> {
-    // 1. If the destination path exists, conditionally prompt user
-    let target_md = match fs::metadata(target) {
-        Ok(md) => Some(md),
-        Err(e) => {
-            if e.kind() == io::ErrorKind::NotFound {
-                None
-            } else {
-                eprintln!("{}: {}", target, e);
-                return Err(e.into());
+fn exchange_files(cfg: &Config, source: &str, target: &str) -> io::Result<()> {
+    match rename_with_flags(Path::new(source), Path::new(target), RENAME_EXCHANGE) {
+        Ok(()) => {
+            if cfg.verbose {
+                println!("exchanged '{}' <-> '{}'", source, target);
             }
+            Ok(())
         }
+
+        // a swap made of copies would not be atomic, so there is no
+        // fallback here
+        Err(e) if is_unsupported(&e, Path::new(source), Path::new(target)) => Err(io::Error::new(
+            e.kind(),
+            format!("{}: {}", gettext("atomic exchange not supported"), e),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+fn move_file(cfg: &Config, source: &str, target: &str) -> io::Result<()> {
+    if cfg.exchange {
+        return exchange_files(cfg, source, target);
+    }
+
+    // 1. If the destination path exists, skip it or conditionally
+    // prompt user
+    let source_md = fs::symlink_metadata(source)?;
+    let target_md = match fs::symlink_metadata(target) {
+        Ok(md) => Some(md),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", target, e))),
     };
-    let target_exists = target_md.is_some();
-    let target_is_dir = match target_md {
-        Some(md) => md.is_dir(),
-        None => false,
-    };
-    if target_exists && !cfg.force && (cfg.is_terminal || cfg.interactive) {
-        let is_affirm = prompt_user(&format!("{}: {}", target, gettext("overwrite?")));
-        if !is_affirm {
+    if let Some(target_md) = &target_md {
+        if cfg.no_clobber {
             return Ok(());
         }
+        if cfg.update
+            && (target_md.mtime(), target_md.mtime_nsec())
+                >= (source_md.mtime(), source_md.mtime_nsec())
+        {
+            return Ok(());
+        }
+        if !cfg.force && (cfg.is_terminal || cfg.interactive) {
+            let is_affirm = prompt_user(&format!("{}: {}", target, gettext("overwrite?")));
+            if !is_affirm {
+                return Ok(());
+            }
+        }
     }
 
     // 2. source and target are same dirent:  we assume rename handles this case
 
-    // 3. call rename(2) to move source to target
-    match fs::rename(source, target) {
-        Ok(_) => return Ok(()),
+    // 3. call rename(2) to move source to target.  Under -n, the target
+    // may have appeared since it was checked above; renameat2(2) with
+    // RENAME_NOREPLACE closes that race.
+    let renamed = if cfg.no_clobber {
+        rename_noreplace(Path::new(source), Path::new(target))
+    } else {
+        fs::rename(source, target).map(|_| true)
+    };
+    match renamed {
+        Ok(true) => {
+            if cfg.verbose {
+                println!("renamed '{}' -> '{}'", source, target);
+            }
+            return Ok(());
+        }
+        Ok(false) => return Ok(()),
         Err(e) => {
             // use ErrorKind::CrossesDevices in the future, when it is stable
-            let errno = std::io::Error::last_os_error().raw_os_error().unwrap();
-            if errno != libc::EXDEV {
-                eprintln!("{}: {}", source, e);
-                return Err(e.into());
+            if e.raw_os_error() != Some(libc::EXDEV) {
+                return Err(e);
             }
         }
     }
@@ -118,60 +377,28 @@ fn move_file(cfg: &Config, source: &str, target: &str) -> io::Result<()> {
     // Fall through: source and target are on different filesystems; must copy.
 
     // 4. handle source/target dir mismatch
-    let source_md = match fs::metadata(source) {
-        Ok(md) => Some(md),
-        Err(e) => {
-            if e.kind() == io::ErrorKind::NotFound {
-                None
+    let source_is_dir = source_md.is_dir();
+    if let Some(target_md) = &target_md {
+        if target_md.is_dir() != source_is_dir {
+            let msg = if target_md.is_dir() {
+                gettext("cannot overwrite directory with non-directory")
             } else {
-                eprintln!("{}: {}", source, e);
-                return Err(e.into());
-            }
-        }
-    };
-    let source_is_dir = match source_md {
-        Some(md) => md.is_dir(),
-        None => false,
-    };
-
-    if (target_exists && target_is_dir && !source_is_dir)
-        || (target_exists && !target_is_dir && source_is_dir)
-    {
-        eprintln!(
-            "{}: {}",
-            target,
-            gettext("cannot overwrite directory with non-directory")
-        );
-        return Ok(());
-    }
-
-    // 5. remove destination path
-    if target_exists {
-        if target_is_dir {
-            fs::remove_dir_all(target)?;
-        } else {
-            fs::remove_file(target)?;
+                gettext("cannot overwrite non-directory with directory")
+            };
+            return Err(io::Error::other(format!("{}: {}", target, msg)));
         }
     }
 
-    // 6. copy source file hierarchy to target
-    if source_is_dir {
-        copy_dir_all(source, target)?;
-    } else {
-        fs::copy(source, target)?;
-    }
-
-    // 7. Remove source file hierarchy
-    assert!(source_is_dir);
-    fs::remove_dir_all(source)?;
-
-    Ok(())
-}
-
-fn move_files(cfg: &Config, sources: &[String], target: &str) -> io::Result<()> {
-    // loop through sources, moving each to target
-    for source in sources {
-        move_file(cfg, source, target)?;
+    // 5. copy source file hierarchy to target, then remove the source
+    let moved = move_across_devices(
+        cfg,
+        Path::new(source),
+        Path::new(target),
+        &source_md,
+        target_md.as_ref(),
+    )?;
+    if moved && cfg.verbose {
+        println!("renamed '{}' -> '{}'", source, target);
     }
 
     Ok(())
@@ -194,27 +421,50 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let sources = &args.files[0..args.files.len() - 1];
     let target = &args.files[args.files.len() - 1];
 
+    // an exchange always swaps exactly the two operands given
+    let plain_target = args.no_target_directory || args.exchange;
+    if plain_target && sources.len() > 1 {
+        eprintln!("mv: {}: {}", target, gettext("extra operand"));
+        std::process::exit(1);
+    }
+
     // choose mode based on whether target is a directory
-    let dir_exists = {
+    let dir_exists = !plain_target && {
         match fs::metadata(target) {
             Ok(md) => md.is_dir(),
             Err(e) => {
                 if e.kind() == io::ErrorKind::NotFound {
                     false
                 } else {
-                    eprintln!("{}: {}", target, e);
+                    eprintln!("mv: {}: {}", target, e);
                     std::process::exit(1);
                 }
             }
         }
     };
 
+    if sources.len() > 1 && !dir_exists {
+        eprintln!("mv: {}: {}", target, gettext("Not a directory"));
+        std::process::exit(1);
+    }
+
     let cfg = Config::new(&args);
-    if dir_exists {
-        let _ = move_files(&cfg, sources, target);
-    } else {
-        let _ = move_file(&cfg, &sources[0], target);
+    let mut exit_code = 0;
+
+    // move each source, carrying on past any that fail
+    for source in sources {
+        let dest = if dir_exists {
+            let name = Path::new(source).file_name().unwrap_or_default();
+            Path::new(target).join(name).to_string_lossy().into_owned()
+        } else {
+            target.clone()
+        };
+
+        if let Err(e) = move_file(&cfg, source, &dest) {
+            eprintln!("mv: {}: {}", source, e);
+            exit_code = 1;
+        }
     }
 
-    Ok(())
+    std::process::exit(exit_code)
 }
@@ -0,0 +1,138 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+use plib::testing::{run_in, temp_dir, temp_dir_in};
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn test_mv_cross_device() {
+    // /dev/shm is a tmpfs on Linux; elsewhere there may be no second
+    // filesystem to move to, and the test is skipped, saying so
+    let shm = Path::new("/dev/shm");
+    let tmp = std::env::temp_dir();
+    let (Ok(shm_md), Ok(tmp_md)) = (fs::metadata(shm), fs::metadata(&tmp)) else {
+        eprintln!("test_mv_cross_device: skipped: /dev/shm or the temp dir is missing");
+        return;
+    };
+    if shm_md.dev() == tmp_md.dev() {
+        eprintln!(
+            "test_mv_cross_device: skipped: /dev/shm and {} are on one filesystem",
+            tmp.display()
+        );
+        return;
+    }
+
+    let src = temp_dir_in(shm, "mv-xdev");
+    let dst = temp_dir("mv-xdev");
+    fs::create_dir_all(src.join("t/sub")).unwrap();
+    fs::write(src.join("t/file"), "file\n").unwrap();
+    fs::hard_link(src.join("t/file"), src.join("t/sub/hard")).unwrap();
+    std::os::unix::fs::symlink("file", src.join("t/link")).unwrap();
+    let fifo = std::ffi::CString::new(src.join("t/fifo").to_str().unwrap()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o644) }, 0);
+    fs::set_permissions(src.join("t/sub"), fs::Permissions::from_mode(0o750)).unwrap();
+
+    let target = dst.join("t");
+    let output = run_in(&src, "mv", &["t", target.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(!src.join("t").exists());
+
+    // hard links are still one file
+    let file_md = fs::metadata(target.join("file")).unwrap();
+    assert_eq!(file_md.nlink(), 2);
+    assert_eq!(
+        fs::metadata(target.join("sub/hard")).unwrap().ino(),
+        file_md.ino()
+    );
+    assert_eq!(
+        fs::read_to_string(target.join("sub/hard")).unwrap(),
+        "file\n"
+    );
+
+    assert_eq!(
+        fs::read_link(target.join("link")).unwrap(),
+        Path::new("file")
+    );
+    assert!(fs::symlink_metadata(target.join("fifo"))
+        .unwrap()
+        .file_type()
+        .is_fifo());
+    assert_eq!(
+        fs::metadata(target.join("sub")).unwrap().mode() & 0o7777,
+        0o750
+    );
+
+    // and back again, for a single file
+    let output = run_in(&dst, "mv", &["t/file", src.join("moved").to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(!target.join("file").exists());
+    assert_eq!(fs::read_to_string(src.join("moved")).unwrap(), "file\n");
+
+    fs::remove_dir_all(&src).unwrap();
+    fs::remove_dir_all(&dst).unwrap();
+}
+
+#[test]
+fn test_mv_options() {
+    let dir = temp_dir("mv-options");
+    fs::write(dir.join("a"), "a\n").unwrap();
+    fs::write(dir.join("b"), "b\n").unwrap();
+
+    // -n never overwrites
+    let output = run_in(&dir, "mv", &["-n", "a", "b"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("a")).unwrap(), "a\n");
+    assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "b\n");
+
+    // -u leaves a newer target alone
+    let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    fs::File::options()
+        .write(true)
+        .open(dir.join("a"))
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+    let output = run_in(&dir, "mv", &["-u", "a", "b"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("a")).unwrap(), "a\n");
+    assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "b\n");
+
+    // but replaces an older one
+    let output = run_in(&dir, "mv", &["-u", "b", "a"]);
+    assert!(output.status.success());
+    assert!(!dir.join("b").exists());
+    assert_eq!(fs::read_to_string(dir.join("a")).unwrap(), "b\n");
+
+    // -T does not move into a directory
+    fs::create_dir(dir.join("d")).unwrap();
+    let output = run_in(&dir, "mv", &["-T", "a", "d"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(dir.join("a").exists());
+    assert!(!dir.join("d/a").exists());
+
+    // a directory moved into itself is an error, not a missing feature
+    fs::create_dir(dir.join("d/sub")).unwrap();
+    let output = run_in(&dir, "mv", &["-n", "d", "d/sub/x"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("not supported"));
+    assert_eq!(fs::read_dir(dir.join("d")).unwrap().count(), 1);
+
+    // --exchange swaps the two names
+    fs::write(dir.join("x"), "x\n").unwrap();
+    fs::write(dir.join("y"), "y\n").unwrap();
+    let output = run_in(&dir, "mv", &["--exchange", "x", "y"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(dir.join("x")).unwrap(), "y\n");
+    assert_eq!(fs::read_to_string(dir.join("y")).unwrap(), "x\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -6,45 +6,168 @@
 // file in the root directory of this project.
 // SPDX-License-Identifier: MIT
 //
-// TODO:
-// - investigate whether Rust crates such as crc32fast provide this
-//   functionality more efficiently.  It was tested, and did not work;
-//   However, it is theorized that the polynomial was correct,
-//   and the source of the error was that the final input data size
-//   was not appended to the CRC calculation.  The likely solution is
-//   a Rust crate + our finalize() function.
-//
 
 extern crate clap;
 extern crate plib;
 
-mod crc32;
-
-use clap::Parser;
-use gettextrs::{bind_textdomain_codeset, textdomain};
+use clap::{Parser, ValueEnum};
+use digest::DynDigest;
+use gettextrs::{bind_textdomain_codeset, gettext, ngettext, textdomain};
+use plib::crc32::Crc32Posix;
 use plib::PROJECT_NAME;
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, BufRead, Read};
+
+/// Checksum algorithms.
+#[derive(Debug, ValueEnum, Clone, Copy, PartialEq)]
+enum Algorithm {
+    /// The POSIX CRC, printed with the file size.
+    Crc,
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    Blake2b,
+}
+
+impl Algorithm {
+    const DIGESTS: [Algorithm; 7] = [
+        Algorithm::Md5,
+        Algorithm::Sha1,
+        Algorithm::Sha224,
+        Algorithm::Sha256,
+        Algorithm::Sha384,
+        Algorithm::Sha512,
+        Algorithm::Blake2b,
+    ];
+
+    /// The name used in BSD-style tagged lines.
+    fn tag(self) -> &'static str {
+        match self {
+            Algorithm::Crc => "CRC",
+            Algorithm::Md5 => "MD5",
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha224 => "SHA224",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha384 => "SHA384",
+            Algorithm::Sha512 => "SHA512",
+            Algorithm::Blake2b => "BLAKE2b",
+        }
+    }
+
+    fn digest(self) -> Box<dyn DynDigest> {
+        match self {
+            Algorithm::Crc => unreachable!(),
+            Algorithm::Md5 => Box::new(md5::Md5::default()),
+            Algorithm::Sha1 => Box::new(sha1::Sha1::default()),
+            Algorithm::Sha224 => Box::new(sha2::Sha224::default()),
+            Algorithm::Sha256 => Box::new(sha2::Sha256::default()),
+            Algorithm::Sha384 => Box::new(sha2::Sha384::default()),
+            Algorithm::Sha512 => Box::new(sha2::Sha512::default()),
+            Algorithm::Blake2b => Box::new(blake2::Blake2b512::default()),
+        }
+    }
+
+    /// Length of the digest, in hex digits.
+    fn hex_len(self) -> usize {
+        match self {
+            Algorithm::Crc => 0,
+            _ => self.digest().output_size() * 2,
+        }
+    }
+}
 
 /// cksum - write file checksums and sizes
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about)]
 struct Args {
+    /// Checksum algorithm to use.
+    #[arg(short, long, value_enum, default_value_t = Algorithm::Crc)]
+    algorithm: Algorithm,
+
+    /// Write digests as "DIGEST  FILE" rather than in the tagged BSD style.
+    #[arg(long)]
+    untagged: bool,
+
+    /// Read checksums from the files and check them.
+    #[arg(short, long)]
+    check: bool,
+
+    /// When checking, do not print OK for each verified file.
+    #[arg(long)]
+    quiet: bool,
+
+    /// When checking, print nothing; the exit status tells the result.
+    #[arg(long)]
+    status: bool,
+
+    /// When checking, fail on improperly formatted checksum lines.
+    #[arg(long)]
+    strict: bool,
+
+    /// When checking, warn about improperly formatted checksum lines.
+    #[arg(short, long)]
+    warn: bool,
+
     /// Files to read as input.  Use "-" or no-args for stdin.
     files: Vec<String>,
 }
 
-fn cksum_file(filename: &str) -> io::Result<()> {
-    let mut file: Box<dyn Read>;
-    if filename == "" {
-        file = Box::new(io::stdin().lock());
+/// The checksum of one file.
+enum Checksum {
+    Crc(u32, u64),
+    Digest(String),
+}
+
+enum Summer {
+    Crc(Crc32Posix),
+    Digest(Box<dyn DynDigest>),
+}
+
+impl Summer {
+    fn new(algorithm: Algorithm) -> Summer {
+        match algorithm {
+            Algorithm::Crc => Summer::Crc(Crc32Posix::new()),
+            _ => Summer::Digest(algorithm.digest()),
+        }
+    }
+
+    fn update(&mut self, buf: &[u8]) {
+        match self {
+            Summer::Crc(crc) => crc.update(buf),
+            Summer::Digest(digest) => digest.update(buf),
+        }
+    }
+
+    fn finish(self) -> Checksum {
+        match self {
+            Summer::Crc(crc) => Checksum::Crc(crc.finalize(), crc.len()),
+            Summer::Digest(digest) => Checksum::Digest(
+                digest
+                    .finalize()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+fn open_input(filename: &str) -> io::Result<Box<dyn Read>> {
+    if filename.is_empty() || filename == "-" {
+        Ok(Box::new(io::stdin().lock()))
     } else {
-        file = Box::new(fs::File::open(filename)?);
+        Ok(Box::new(fs::File::open(filename)?))
     }
+}
+
+fn cksum_file(filename: &str, algorithm: Algorithm) -> io::Result<Checksum> {
+    let mut file = open_input(filename)?;
 
-    let mut buffer = [0; plib::BUFSZ];
-    let mut n_bytes: u64 = 0;
-    let mut crc: u32 = 0;
+    let mut buffer = vec![0; plib::copy::COPY_BUFSZ];
+    let mut summer = Summer::new(algorithm);
 
     loop {
         let n_read = file.read(&mut buffer[..])?;
@@ -52,26 +175,186 @@ fn cksum_file(filename: &str) -> io::Result<()> {
             break;
         }
 
-        n_bytes = n_bytes + n_read as u64;
-        crc = crc32::update(crc, &buffer[0..n_read]);
+        summer.update(&buffer[0..n_read]);
     }
 
-    let filename_prefix = {
-        if filename == "" {
-            ""
-        } else {
-            " "
+    Ok(summer.finish())
+}
+
+fn print_checksum(args: &Args, filename: &str, sum: &Checksum) {
+    match sum {
+        Checksum::Crc(crc, len) => {
+            let filename_prefix = if filename.is_empty() { "" } else { " " };
+            println!("{} {}{}{}", crc, len, filename_prefix, filename);
         }
+        Checksum::Digest(hex) => {
+            let name = if filename.is_empty() { "-" } else { filename };
+            if args.untagged {
+                println!("{}  {}", hex, name);
+            } else {
+                println!("{} ({}) = {}", args.algorithm.tag(), name, hex);
+            }
+        }
+    }
+}
+
+/// Parse one line of a checksum list: either tagged, "ALGO (FILE) = DIGEST",
+/// or untagged, "DIGEST  FILE", which is read as the -a algorithm.  CRC
+/// lines cannot be checked, so an untagged list needs some other -a.
+fn parse_check_line(line: &str, default: Algorithm) -> Option<(Algorithm, &str, &str)> {
+    for algorithm in Algorithm::DIGESTS {
+        let Some(rest) = line.strip_prefix(algorithm.tag()) else {
+            continue;
+        };
+        let Some(rest) = rest.strip_prefix(" (") else {
+            continue;
+        };
+        let (name, hex) = rest.rsplit_once(") = ")?;
+        if hex.len() != algorithm.hex_len() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        return Some((algorithm, hex, name));
+    }
+
+    if default == Algorithm::Crc {
+        return None;
+    }
+    let hex_len = default.hex_len();
+    let hex = line.get(..hex_len)?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    // a '*' in place of the second space marks binary mode, which is
+    // no different here
+    let name = line[hex_len..]
+        .strip_prefix("  ")
+        .or_else(|| line[hex_len..].strip_prefix(" *"))?;
+    if name.is_empty() {
+        return None;
+    }
+    Some((default, hex, name))
+}
+
+#[derive(Default)]
+struct CheckTotals {
+    mismatched: usize,
+    unreadable: usize,
+    improper: usize,
+}
+
+/// Check the checksums listed in one file.  Returns false if the list
+/// holds no checksum lines at all.
+fn check_list(args: &Args, listname: &str, totals: &mut CheckTotals) -> io::Result<bool> {
+    let reader = io::BufReader::new(open_input(listname)?);
+    let listname = if listname.is_empty() || listname == "-" {
+        gettext("standard input")
+    } else {
+        listname.to_string()
     };
-    println!(
-        "{} {}{}{}",
-        crc32::finalize(crc, n_bytes as usize),
-        n_bytes,
-        filename_prefix,
-        filename
-    );
-
-    Ok(())
+
+    let mut found = false;
+    for (lineno, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((algorithm, expected, filename)) = parse_check_line(line, args.algorithm) else {
+            totals.improper += 1;
+            if args.warn {
+                eprintln!(
+                    "cksum: {}: {}: {}",
+                    listname,
+                    lineno + 1,
+                    gettext("improperly formatted checksum line")
+                );
+            }
+            continue;
+        };
+        found = true;
+
+        match cksum_file(filename, algorithm) {
+            Ok(Checksum::Digest(hex)) if hex.eq_ignore_ascii_case(expected) => {
+                if !args.quiet && !args.status {
+                    println!("{}: {}", filename, gettext("OK"));
+                }
+            }
+            Ok(_) => {
+                totals.mismatched += 1;
+                if !args.status {
+                    println!("{}: {}", filename, gettext("FAILED"));
+                }
+            }
+            Err(e) => {
+                totals.unreadable += 1;
+                if !args.status {
+                    eprintln!("cksum: {}: {}", filename, e);
+                    println!("{}: {}", filename, gettext("FAILED open or read"));
+                }
+            }
+        }
+    }
+
+    if !found {
+        eprintln!(
+            "cksum: {}: {}",
+            listname,
+            gettext("no properly formatted checksum lines found")
+        );
+    }
+    Ok(found)
+}
+
+/// Check every list, print the summary warnings, and return the exit status.
+fn check_lists(args: &Args) -> i32 {
+    let mut totals = CheckTotals::default();
+    let mut exit_code = 0;
+
+    for listname in &args.files {
+        match check_list(args, listname, &mut totals) {
+            Ok(true) => {}
+            Ok(false) => exit_code = 1,
+            Err(e) => {
+                exit_code = 1;
+                eprintln!("cksum: {}: {}", listname, e);
+            }
+        }
+    }
+
+    if !args.status {
+        if totals.improper > 0 {
+            let msg = ngettext(
+                "line is improperly formatted",
+                "lines are improperly formatted",
+                totals.improper as u32,
+            );
+            eprintln!("cksum: WARNING: {} {}", totals.improper, msg);
+        }
+        if totals.unreadable > 0 {
+            let msg = ngettext(
+                "listed file could not be read",
+                "listed files could not be read",
+                totals.unreadable as u32,
+            );
+            eprintln!("cksum: WARNING: {} {}", totals.unreadable, msg);
+        }
+        if totals.mismatched > 0 {
+            let msg = ngettext(
+                "computed checksum did NOT match",
+                "computed checksums did NOT match",
+                totals.mismatched as u32,
+            );
+            eprintln!("cksum: WARNING: {} {}", totals.mismatched, msg);
+        }
+    }
+
+    if totals.mismatched > 0 || totals.unreadable > 0 || (args.strict && totals.improper > 0) {
+        exit_code = 1;
+    }
+    exit_code
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -86,12 +369,19 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         args.files.push(String::new());
     }
 
+    if args.check {
+        std::process::exit(check_lists(&args));
+    }
+
     let mut exit_code = 0;
 
     for filename in &args.files {
-        if let Err(e) = cksum_file(filename) {
-            exit_code = 1;
-            eprintln!("{}: {}", filename, e);
+        match cksum_file(filename, args.algorithm) {
+            Ok(sum) => print_checksum(&args, filename, &sum),
+            Err(e) => {
+                exit_code = 1;
+                eprintln!("{}: {}", filename, e);
+            }
         }
     }
 
@@ -0,0 +1,210 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+extern crate clap;
+extern crate plib;
+
+// the decoder half is used by uncompress
+#[allow(dead_code)]
+mod lzw;
+mod zfile;
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
+use lzw::UnixLZWWriter;
+use plib::PROJECT_NAME;
+use std::fs;
+use std::io::{self, Read, Write};
+use zfile::{copy_metadata, create_output, prompt_user};
+
+/// compress - compress data
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about)]
+struct Args {
+    /// Specify the maximum number of bits to use in a code.
+    #[arg(short, long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(9..=16))]
+    bits: u32,
+
+    /// Write to the standard output; the input files are not changed.
+    #[arg(short = 'c', long)]
+    stdout: bool,
+
+    /// Overwrite existing files without prompting, and replace files even if compression does not make them smaller.
+    #[arg(short, long)]
+    force: bool,
+
+    /// Write the percentage reduction of each file to standard error.
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Files to compress.  With none, compress the standard input to the standard output.
+    files: Vec<String>,
+}
+
+/// How compressing one file turned out.
+enum Outcome {
+    Done,
+
+    // compressing did not make the file smaller; it was left alone
+    NotSmaller,
+}
+
+/// Compress all of `input` to `output`, returning the bytes read and
+/// written.
+fn compress_stream(
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+    bits: u32,
+) -> io::Result<(u64, u64)> {
+    let mut encoder = UnixLZWWriter::new(output, bits);
+    let mut buffer = vec![0; plib::BUFSZ];
+
+    loop {
+        let n_read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        encoder.write(&buffer[..n_read])?;
+    }
+    encoder.finish()?;
+
+    Ok((encoder.bytes_in(), encoder.bytes_out()))
+}
+
+fn percent_saved(bytes_in: u64, bytes_out: u64) -> f64 {
+    if bytes_in == 0 {
+        0.0
+    } else {
+        100.0 * (bytes_in as f64 - bytes_out as f64) / bytes_in as f64
+    }
+}
+
+fn compress_file(args: &Args, filename: &str) -> io::Result<Outcome> {
+    let mut input = fs::File::open(filename)?;
+    let md = input.metadata()?;
+
+    if args.stdout {
+        let mut out = io::stdout().lock();
+        let (bytes_in, bytes_out) = compress_stream(&mut input, &mut out, args.bits)?;
+        if args.verbose {
+            eprintln!(
+                "{}: {}: {:.2}%",
+                filename,
+                gettext("Compression"),
+                percent_saved(bytes_in, bytes_out)
+            );
+        }
+        return Ok(Outcome::Done);
+    }
+
+    if !md.is_file() {
+        return Err(io::Error::other(gettext("not a regular file: unchanged")));
+    }
+    if filename.ends_with(".Z") {
+        return Err(io::Error::other(gettext(
+            "already has .Z suffix: unchanged",
+        )));
+    }
+
+    let zname = format!("{}.Z", filename);
+    if fs::symlink_metadata(&zname).is_ok() && !args.force {
+        let overwrite = atty::is(atty::Stream::Stdin)
+            && prompt_user(&format!(
+                "{} {}; {}",
+                zname,
+                gettext("already exists"),
+                gettext("overwrite (y/n)?")
+            ));
+        if !overwrite {
+            return Err(io::Error::other(format!(
+                "{}: {}",
+                zname,
+                gettext("already exists; not overwritten")
+            )));
+        }
+    }
+
+    let mut output = create_output(&zname)?;
+    let res = compress_stream(&mut input, &mut io::BufWriter::new(&mut output), args.bits);
+    let (bytes_in, bytes_out) = match res {
+        Ok(counts) => counts,
+        Err(e) => {
+            let _ = fs::remove_file(&zname);
+            return Err(e);
+        }
+    };
+
+    let saved = percent_saved(bytes_in, bytes_out);
+    if bytes_out >= bytes_in && !args.force {
+        fs::remove_file(&zname)?;
+        if args.verbose {
+            eprintln!(
+                "{}: {}: {:.2}% -- {}",
+                filename,
+                gettext("Compression"),
+                saved,
+                gettext("file unchanged")
+            );
+        }
+        return Ok(Outcome::NotSmaller);
+    }
+
+    copy_metadata(&md, &output)?;
+    fs::remove_file(filename)?;
+
+    if args.verbose {
+        eprintln!(
+            "{}: {}: {:.2}% -- {} {}",
+            filename,
+            gettext("Compression"),
+            saved,
+            gettext("replaced with"),
+            zname
+        );
+    }
+    Ok(Outcome::Done)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // parse command line arguments
+    let args = Args::parse();
+
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    if args.files.is_empty() {
+        let mut out = io::stdout().lock();
+        if let Err(e) = compress_stream(&mut io::stdin().lock(), &mut out, args.bits) {
+            eprintln!("compress: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let mut exit_code = 0;
+
+    for filename in &args.files {
+        match compress_file(&args, filename) {
+            Ok(Outcome::Done) => {}
+            Ok(Outcome::NotSmaller) => {
+                if exit_code == 0 {
+                    exit_code = 2;
+                }
+            }
+            Err(e) => {
+                exit_code = 1;
+                eprintln!("compress: {}: {}", filename, e);
+            }
+        }
+    }
+
+    std::process::exit(exit_code)
+}
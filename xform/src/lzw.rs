@@ -9,11 +9,8 @@
 // History:  Adapted from posixutils/compress/zopen.cc, which was in turn
 // adapted from FreeBSD's zopen.c.
 //
-// TODO:
-// - FIXME: file tail truncated (data corruption)
-//
 
-use std::io::{self, Error, ErrorKind, Read};
+use std::io::{self, BufReader, Error, ErrorKind, Read, Write};
 
 const INIT_BITS: u32 = 9;
 const HSIZE: usize = 69001;
@@ -26,10 +23,20 @@ const CLEAR: i32 = 256;
 
 const RMASK: [i32; 9] = [0x00, 0x01, 0x03, 0x07, 0x0f, 0x1f, 0x3f, 0x7f, 0xff];
 
+// how much to decode at a time
+const READ_CHUNK: usize = 64 * 1024;
+
+// how much input to take between checks of the compression ratio
+const CHECK_GAP: u64 = 10000;
+
 fn max_code(n_bits: u32) -> u32 {
     (1 << (n_bits)) - 1
 }
 
+fn unexpected_eof() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "unexpected end of file")
+}
+
 pub struct UnixLZWReader {
     rdr: Box<dyn Read>,
     have_hdr: bool,
@@ -56,7 +63,8 @@ pub struct UnixLZWReader {
 impl UnixLZWReader {
     pub fn new(rdr: Box<dyn Read>) -> UnixLZWReader {
         UnixLZWReader {
-            rdr,
+            // codes are read a few bytes at a time
+            rdr: Box::new(BufReader::new(rdr)),
             have_hdr: false,
             eof: false,
             maxbits: 0,
@@ -78,11 +86,12 @@ impl UnixLZWReader {
         }
     }
 
-    fn getcode(&mut self) -> i32 {
+    /// Read the next code, or None at the end of the input.
+    fn getcode(&mut self) -> io::Result<Option<i32>> {
         if self.clear || self.roffset >= self.size || self.free_ent > self.maxcode {
             if self.free_ent > self.maxcode {
                 self.n_bits = self.n_bits + 1;
-                if self.n_bits == self.maxcode as u32 {
+                if self.n_bits == self.maxbits {
                     self.maxcode = self.maxmaxcode;
                 } else {
                     self.maxcode = max_code(self.n_bits) as i32;
@@ -95,14 +104,35 @@ impl UnixLZWReader {
                 self.clear = false;
             }
 
+            // the last group of codes is cut short, after the last
+            // byte that holds any of them
             let gbuf = &mut self.gbuf[0..self.n_bits as usize];
+            let mut n_read = 0;
+            while n_read < gbuf.len() {
+                match self.rdr.read(&mut gbuf[n_read..]) {
+                    Ok(0) => break,
+                    Ok(n) => n_read += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            if n_read == 0 {
+                return Ok(None);
+            }
 
-            let res = self.rdr.read_exact(gbuf);
-            if res.is_err() {
-                return -1;
+            // The encoder ends the last group after the byte holding the
+            // last bit of the last code, leaving the bits above it clear.
+            // Anything else is input that stops partway through a code.
+            if n_read < gbuf.len() {
+                let total_bits = (n_read * 8) as u32;
+                let spare = total_bits % self.n_bits;
+                let last = gbuf[n_read - 1] as u32;
+                if spare >= 8 || last >> (8 - spare) != 0 {
+                    return Err(unexpected_eof());
+                }
             }
 
-            self.size = gbuf.len() as i32;
+            self.size = n_read as i32;
             self.roffset = 0;
             self.size = (self.size << 3) - (self.n_bits - 1) as i32;
         }
@@ -126,19 +156,33 @@ impl UnixLZWReader {
             bits = bits - 8;
         }
 
-        gcode = gcode | (((self.gbuf[bp] as i32) & RMASK[bits as usize]) << r_off);
+        // a code that ends on a byte boundary may end the buffer, too
+        if bits > 0 {
+            gcode = gcode | (((self.gbuf[bp] as i32) & RMASK[bits as usize]) << r_off);
+        }
         self.roffset = self.roffset + self.n_bits as i32;
 
-        gcode
+        Ok(Some(gcode))
     }
 
+    /// Decode the next stretch of data, returning an empty vector at
+    /// the end of the input.
     pub fn read(&mut self) -> io::Result<Vec<u8>> {
         let mut outbytes: Vec<u8> = Vec::new();
+        if self.eof {
+            return Ok(outbytes);
+        }
 
         if !self.have_hdr {
             // 3-byte header.  2 byte magic, 1 byte a bitmask of options.
             let mut header = [0; 3];
-            self.rdr.read_exact(&mut header)?;
+            self.rdr.read_exact(&mut header).map_err(|e| {
+                if e.kind() == ErrorKind::UnexpectedEof {
+                    unexpected_eof()
+                } else {
+                    e
+                }
+            })?;
 
             let header_magic = &header[0..2];
 
@@ -176,24 +220,25 @@ impl UnixLZWReader {
 
             self.have_hdr = true;
 
-            self.oldcode = self.getcode();
-            self.finchar = self.oldcode;
-            if self.oldcode < 0 {
+            let Some(code) = self.getcode()? else {
                 self.eof = true;
                 return Ok(Vec::new());
-            }
+            };
+            self.oldcode = code;
+            self.finchar = self.oldcode;
 
             outbytes.push(self.finchar as u8);
         }
 
+        let mut stack: Vec<u8> = Vec::new();
         loop {
-            let mut stack: Vec<u8> = Vec::new();
+            stack.clear();
 
-            self.code = self.getcode();
-            if self.code < 0 {
+            let Some(code) = self.getcode()? else {
                 self.eof = true;
                 break;
-            }
+            };
+            self.code = code;
 
             if (self.code == CLEAR) && self.block_compress {
                 for code in (0..=255).rev() {
@@ -204,14 +249,17 @@ impl UnixLZWReader {
                 self.clear = true;
                 self.free_ent = FIRST - 1;
 
-                self.code = self.getcode();
-                if self.code < 0 {
+                let Some(code) = self.getcode()? else {
                     self.eof = true;
                     break;
-                }
+                };
+                self.code = code;
             }
             self.incode = self.code;
 
+            if self.code > self.free_ent {
+                return Err(Error::new(ErrorKind::InvalidData, "corrupt input"));
+            }
             if self.code >= self.free_ent {
                 stack.push(self.finchar as u8);
                 self.code = self.oldcode;
@@ -225,9 +273,7 @@ impl UnixLZWReader {
             self.finchar = self.tab_suffix[self.code as usize];
             stack.push(self.finchar as u8);
 
-            for stackchar in stack.iter().rev() {
-                outbytes.push(*stackchar);
-            }
+            outbytes.extend(stack.iter().rev());
 
             self.code = self.free_ent;
             if self.code < self.maxmaxcode {
@@ -238,8 +284,250 @@ impl UnixLZWReader {
             }
 
             self.oldcode = self.incode;
+
+            if outbytes.len() >= READ_CHUNK {
+                break;
+            }
         }
 
         Ok(outbytes)
     }
 }
+
+/// Compresses into the format read by UnixLZWReader.
+pub struct UnixLZWWriter<W: Write> {
+    wtr: W,
+    started: bool,
+
+    maxbits: u32,
+    n_bits: u32,
+    maxcode: i32,
+    maxmaxcode: i32,
+    free_ent: i32,
+    clear_flg: bool,
+    ent: i32,
+
+    // the compression ratio last seen, and when to look again
+    ratio: u64,
+    checkpoint: u64,
+    in_count: u64,
+    bytes_out: u64,
+
+    // the group of codes being assembled, all of n_bits wide
+    offset: u32,
+    gbuf: [u8; BITS as usize],
+    outbuf: Vec<u8>,
+
+    htab: Vec<i64>,
+    codetab: Vec<u16>,
+}
+
+impl<W: Write> UnixLZWWriter<W> {
+    pub fn new(wtr: W, maxbits: u32) -> UnixLZWWriter<W> {
+        UnixLZWWriter {
+            wtr,
+            started: false,
+            maxbits,
+            n_bits: INIT_BITS,
+            maxcode: max_code(INIT_BITS) as i32,
+            maxmaxcode: 1 << maxbits,
+            free_ent: FIRST,
+            clear_flg: false,
+            ent: 0,
+            ratio: 0,
+            checkpoint: CHECK_GAP,
+            in_count: 0,
+            bytes_out: 0,
+            offset: 0,
+            gbuf: [0; BITS as usize],
+            outbuf: Vec::new(),
+            htab: vec![-1; HSIZE],
+            codetab: vec![0; HSIZE],
+        }
+    }
+
+    /// The number of bytes taken in so far.
+    pub fn bytes_in(&self) -> u64 {
+        self.in_count
+    }
+
+    /// The number of compressed bytes produced so far.
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out
+    }
+
+    fn put_bytes(&mut self, n: usize) -> io::Result<()> {
+        self.outbuf.extend_from_slice(&self.gbuf[..n]);
+        self.bytes_out += n as u64;
+        if self.outbuf.len() >= 64 * 1024 {
+            self.wtr.write_all(&self.outbuf)?;
+            self.outbuf.clear();
+        }
+        Ok(())
+    }
+
+    /// Add a code to the current group.  The code width changes only at
+    /// the end of a group, which is written out whole, as the reader
+    /// always reads n_bits bytes at a time.
+    fn output(&mut self, code: i32) -> io::Result<()> {
+        let mut code = code as u32;
+        let mut bits = self.n_bits;
+        while bits > 0 {
+            let byte = (self.offset >> 3) as usize;
+            let shift = self.offset & 7;
+            let take = bits.min(8 - shift);
+            if shift == 0 {
+                self.gbuf[byte] = 0;
+            }
+            self.gbuf[byte] |= ((code & ((1 << take) - 1)) << shift) as u8;
+            code >>= take;
+            bits -= take;
+            self.offset += take;
+        }
+
+        if self.offset == self.n_bits << 3 {
+            self.put_bytes(self.n_bits as usize)?;
+            self.offset = 0;
+        }
+
+        if self.free_ent > self.maxcode || self.clear_flg {
+            if self.offset > 0 {
+                self.put_bytes(self.n_bits as usize)?;
+                self.offset = 0;
+            }
+
+            if self.clear_flg {
+                self.n_bits = INIT_BITS;
+                self.maxcode = max_code(self.n_bits) as i32;
+                self.clear_flg = false;
+            } else {
+                self.n_bits += 1;
+                if self.n_bits == self.maxbits {
+                    self.maxcode = self.maxmaxcode;
+                } else {
+                    self.maxcode = max_code(self.n_bits) as i32;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Once the table is full, watch the compression ratio, and start
+    /// over with an empty table when it begins to fall.
+    fn cl_block(&mut self) -> io::Result<()> {
+        self.checkpoint = self.in_count + CHECK_GAP;
+
+        let rat = if self.in_count > 0x007f_ffff {
+            match self.bytes_out >> 8 {
+                0 => 0x7fff_ffff,
+                n => self.in_count / n,
+            }
+        } else {
+            (self.in_count << 8) / self.bytes_out
+        };
+
+        if rat > self.ratio {
+            self.ratio = rat;
+        } else {
+            self.ratio = 0;
+            self.htab.fill(-1);
+            self.free_ent = FIRST;
+            self.clear_flg = true;
+            self.output(CLEAR)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        let mut bytes = buf.iter();
+
+        if !self.started {
+            let Some(&first) = bytes.next() else {
+                return Ok(());
+            };
+
+            let header = [
+                MAGIC_HEADER[0],
+                MAGIC_HEADER[1],
+                self.maxbits as u8 | HDR_BLOCK_MASK,
+            ];
+            self.outbuf.extend_from_slice(&header);
+            self.bytes_out = header.len() as u64;
+            self.ent = first as i32;
+            self.in_count = 1;
+            self.started = true;
+        }
+
+        for &c in bytes {
+            self.in_count += 1;
+            let fcode = ((c as i64) << self.maxbits) + self.ent as i64;
+            let mut i = ((c as usize) << 8) ^ self.ent as usize;
+
+            if self.htab[i] == fcode {
+                self.ent = self.codetab[i] as i32;
+                continue;
+            }
+
+            // secondary probe
+            let mut found = false;
+            if self.htab[i] >= 0 {
+                let disp = if i == 0 { 1 } else { HSIZE - i };
+                loop {
+                    i = if i >= disp {
+                        i - disp
+                    } else {
+                        i + HSIZE - disp
+                    };
+                    if self.htab[i] == fcode {
+                        found = true;
+                        break;
+                    }
+                    if self.htab[i] < 0 {
+                        break;
+                    }
+                }
+            }
+            if found {
+                self.ent = self.codetab[i] as i32;
+                continue;
+            }
+
+            self.output(self.ent)?;
+            self.ent = c as i32;
+            if self.free_ent < self.maxmaxcode {
+                self.codetab[i] = self.free_ent as u16;
+                self.free_ent += 1;
+                self.htab[i] = fcode;
+            } else if self.in_count >= self.checkpoint {
+                self.cl_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write out the last code and whatever is buffered.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.started {
+            // the header alone stands for no data
+            let header = [
+                MAGIC_HEADER[0],
+                MAGIC_HEADER[1],
+                self.maxbits as u8 | HDR_BLOCK_MASK,
+            ];
+            self.outbuf.extend_from_slice(&header);
+            self.bytes_out = header.len() as u64;
+            self.started = true;
+        } else {
+            self.output(self.ent)?;
+            if self.offset > 0 {
+                let n = self.offset.div_ceil(8) as usize;
+                self.put_bytes(n)?;
+                self.offset = 0;
+            }
+        }
+
+        self.wtr.write_all(&self.outbuf)?;
+        self.outbuf.clear();
+        self.wtr.flush()
+    }
+}
@@ -6,39 +6,60 @@
 // file in the root directory of this project.
 // SPDX-License-Identifier: MIT
 //
-// TODO:
-// - FIXME: file tail truncated (data corruption)
-//
 
 extern crate clap;
 extern crate plib;
 
+// the encoder half is used by compress
+#[allow(dead_code)]
 mod lzw;
+mod zfile;
 
 use clap::Parser;
-use gettextrs::{bind_textdomain_codeset, textdomain};
+use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
 use lzw::UnixLZWReader;
 use plib::PROJECT_NAME;
 use std::fs;
-use std::io::{self, Read, Write};
+use std::io::{self, Write};
+use zfile::{copy_metadata, create_output, prompt_user};
 
-/// cksum - write file checksums and sizes
+/// uncompress - expand compressed data
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about)]
 struct Args {
-    /// Files to read as input.  Use "-" or no-args for stdin.
+    /// Write to the standard output; the compressed files are not changed.
+    #[arg(short = 'c', long)]
+    stdout: bool,
+
+    /// Overwrite existing files without prompting.
+    #[arg(short, long)]
+    force: bool,
+
+    /// Write the name and percentage reduction of each file to standard error.
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Files to expand; ".Z" is added to names that lack it.  With none, expand the standard input to the standard output.
     files: Vec<String>,
 }
 
-fn uncompress_file(filename: &str) -> io::Result<()> {
-    let file: Box<dyn Read>;
-    if filename == "" {
-        file = Box::new(io::stdin().lock());
-    } else {
-        file = Box::new(fs::File::open(filename)?);
+/// The compressed file named by an operand, and the file it expands to.
+fn file_names(operand: &str) -> (String, String) {
+    match operand.strip_suffix(".Z") {
+        Some(name) => (operand.to_string(), name.to_string()),
+        None => (format!("{}.Z", operand), operand.to_string()),
     }
+}
 
-    let mut decoder = UnixLZWReader::new(file);
+/// Write out what the decoder has left, returning the total number
+/// of bytes written, including `first`, already decoded.
+fn write_decoded(
+    decoder: &mut UnixLZWReader,
+    first: &[u8],
+    out: &mut dyn Write,
+) -> io::Result<u64> {
+    out.write_all(first)?;
+    let mut total = first.len() as u64;
 
     loop {
         let buf = decoder.read()?;
@@ -46,30 +67,123 @@ fn uncompress_file(filename: &str) -> io::Result<()> {
             break;
         }
 
-        io::stdout().write_all(&buf)?;
+        out.write_all(&buf)?;
+        total += buf.len() as u64;
+    }
+
+    out.flush()?;
+    Ok(total)
+}
+
+fn percent_saved(expanded: u64, compressed: u64) -> f64 {
+    if expanded == 0 {
+        0.0
+    } else {
+        100.0 * (expanded as f64 - compressed as f64) / expanded as f64
+    }
+}
+
+fn uncompress_file(args: &Args, operand: &str) -> io::Result<()> {
+    let (zname, name) = file_names(operand);
+    let annotate = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", zname, e));
+
+    let file = fs::File::open(&zname).map_err(annotate)?;
+    let md = file.metadata().map_err(annotate)?;
+    if !args.stdout && !md.is_file() {
+        return Err(annotate(io::Error::other(gettext(
+            "not a regular file: unchanged",
+        ))));
     }
 
+    // the header is checked before any output file is touched
+    let mut decoder = UnixLZWReader::new(Box::new(file));
+    let first = decoder.read().map_err(annotate)?;
+
+    if args.stdout {
+        let total =
+            write_decoded(&mut decoder, &first, &mut io::stdout().lock()).map_err(annotate)?;
+        if args.verbose {
+            eprintln!("{}: {:.2}%", zname, percent_saved(total, md.len()));
+        }
+        return Ok(());
+    }
+
+    if fs::symlink_metadata(&name).is_ok() && !args.force {
+        let overwrite = atty::is(atty::Stream::Stdin)
+            && prompt_user(&format!(
+                "{} {}; {}",
+                name,
+                gettext("already exists"),
+                gettext("overwrite (y/n)?")
+            ));
+        if !overwrite {
+            return Err(io::Error::other(format!(
+                "{}: {}",
+                name,
+                gettext("already exists; not overwritten")
+            )));
+        }
+    }
+
+    let mut output =
+        create_output(&name).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+
+    // a file that fails to expand is not left behind half-written
+    let res = write_decoded(&mut decoder, &first, &mut io::BufWriter::new(&mut output));
+    let total = match res {
+        Ok(total) => total,
+        Err(e) => {
+            let _ = fs::remove_file(&name);
+            return Err(annotate(e));
+        }
+    };
+
+    // write_decoded only returns once the decoder has reached a clean
+    // end of stream, so the compressed file is no longer needed
+    copy_metadata(&md, &output)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+    fs::remove_file(&zname).map_err(annotate)?;
+
+    if args.verbose {
+        eprintln!(
+            "{}: {:.2}% -- {} {}",
+            zname,
+            percent_saved(total, md.len()),
+            gettext("replaced with"),
+            name
+        );
+    }
+    Ok(())
+}
+
+fn uncompress_stdin() -> io::Result<()> {
+    let mut decoder = UnixLZWReader::new(Box::new(io::stdin().lock()));
+    let first = decoder.read()?;
+    write_decoded(&mut decoder, &first, &mut io::stdout().lock())?;
     Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // parse command line arguments
-    let mut args = Args::parse();
+    let args = Args::parse();
 
     textdomain(PROJECT_NAME)?;
     bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
 
-    // if no file args, read from stdin
     if args.files.is_empty() {
-        args.files.push(String::new());
+        if let Err(e) = uncompress_stdin() {
+            eprintln!("uncompress: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
     }
 
     let mut exit_code = 0;
 
-    for filename in &args.files {
-        if let Err(e) = uncompress_file(filename) {
+    for operand in &args.files {
+        if let Err(e) = uncompress_file(&args, operand) {
             exit_code = 1;
-            eprintln!("{}: {}", filename, e);
+            eprintln!("uncompress: {}", e);
         }
     }
 
@@ -9,14 +9,15 @@
 
 extern crate clap;
 extern crate plib;
-extern crate uuencode;
 
 use base64::prelude::*;
 use clap::Parser;
-use gettextrs::{bind_textdomain_codeset, textdomain};
+use gettextrs::{bind_textdomain_codeset, gettext, textdomain};
 use plib::PROJECT_NAME;
 use std::fs::{self, OpenOptions};
-use std::io::{self, Error, ErrorKind, Read, Write};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
 
 /// uudecode - decode a binary file
 #[derive(Parser, Debug)]
@@ -30,56 +31,268 @@ struct Args {
     file: Option<String>,
 }
 
-fn write_file(filename: &str, bindata: &[u8]) -> io::Result<()> {
-    let f_res = OpenOptions::new()
-        .read(false)
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(filename);
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Historical,
+    Base64,
+}
+
+/// What the begin line says.
+struct Header {
+    format: Format,
+    mode: u32,
+    pathname: String,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, gettext(msg))
+}
+
+/// Read one line, without its line ending.  Returns false at the end
+/// of the input.
+fn read_line(input: &mut dyn BufRead, line: &mut Vec<u8>) -> io::Result<bool> {
+    line.clear();
+    if input.read_until(b'\n', line)? == 0 {
+        return Ok(false);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(true)
+}
+
+/// Parse "begin MODE PATHNAME" or "begin-base64 MODE PATHNAME".
+fn parse_header(line: &[u8]) -> Option<Header> {
+    let line = std::str::from_utf8(line).ok()?;
+    let (format, rest) = if let Some(rest) = line.strip_prefix("begin-base64 ") {
+        (Format::Base64, rest)
+    } else if let Some(rest) = line.strip_prefix("begin ") {
+        (Format::Historical, rest)
+    } else {
+        return None;
+    };
+
+    let (mode, pathname) = rest.split_once(' ')?;
+    let mode = u32::from_str_radix(mode, 8).ok()?;
+    if pathname.is_empty() {
+        return None;
+    }
+
+    Some(Header {
+        format,
+        mode: mode & 0o777,
+        pathname: pathname.to_string(),
+    })
+}
 
-    match f_res {
-        Err(e) => {
-            eprintln!("{}: {}", filename, e);
-            return Err(e);
+/// Skip anything before the begin line, such as mail headers.
+fn find_header(input: &mut dyn BufRead) -> io::Result<Header> {
+    let mut line = Vec::new();
+    while read_line(input, &mut line)? {
+        if let Some(header) = parse_header(&line) {
+            return Ok(header);
         }
-        Ok(mut file) => file.write_all(bindata),
     }
+    Err(invalid("no 'begin' line"))
+}
+
+fn uu_value(c: u8) -> io::Result<u8> {
+    // a space and a '`' both stand for zero
+    if !(b' '..=b'`').contains(&c) {
+        return Err(invalid("invalid character in encoded line"));
+    }
+    Ok((c - b' ') & 0o77)
+}
+
+/// Decode the body of the historical format, up to and including the
+/// "end" line.
+fn decode_historical(input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<()> {
+    let mut line = Vec::new();
+    let mut data = Vec::new();
+    loop {
+        if !read_line(input, &mut line)? {
+            return Err(invalid("no 'end' line"));
+        }
+        if line.is_empty() {
+            return Err(invalid("short line in encoded data"));
+        }
+
+        let len = uu_value(line[0])? as usize;
+        if len == 0 {
+            break;
+        }
+
+        // some encoders drop trailing spaces, which stand for zeros
+        let mut chars = line[1..].to_vec();
+        let needed = len.div_ceil(3) * 4;
+        if chars.len() < needed {
+            chars.resize(needed, b' ');
+        }
+
+        data.clear();
+        for group in chars[..needed].chunks(4) {
+            let c0 = uu_value(group[0])?;
+            let c1 = uu_value(group[1])?;
+            let c2 = uu_value(group[2])?;
+            let c3 = uu_value(group[3])?;
+            data.push((c0 << 2) | (c1 >> 4));
+            data.push((c1 << 4) | (c2 >> 2));
+            data.push((c2 << 6) | c3);
+        }
+        out.write_all(&data[..len])?;
+    }
+
+    if !read_line(input, &mut line)? || line != b"end" {
+        return Err(invalid("no 'end' line"));
+    }
+    Ok(())
+}
+
+/// Decode the body of the base64 format, up to and including the
+/// "====" line.  Lines may be of any length; whatever does not fill a
+/// group of four characters is carried over to the next line.
+fn decode_base64(input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<()> {
+    let mut line = Vec::new();
+    let mut pending = Vec::new();
+    loop {
+        if !read_line(input, &mut line)? {
+            return Err(invalid("no '====' line"));
+        }
+        if line == b"====" {
+            break;
+        }
+
+        pending.extend(line.iter().filter(|c| !c.is_ascii_whitespace()));
+        let whole = pending.len() - pending.len() % 4;
+        let data = BASE64_STANDARD
+            .decode(&pending[..whole])
+            .map_err(|_| invalid("invalid base64 data"))?;
+        out.write_all(&data)?;
+        pending.drain(..whole);
+    }
+
+    if !pending.is_empty() {
+        return Err(invalid("invalid base64 data"));
+    }
+    Ok(())
+}
+
+fn decode_body(header: &Header, input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<()> {
+    match header.format {
+        Format::Historical => decode_historical(input, out)?,
+        Format::Base64 => decode_base64(input, out)?,
+    }
+    out.flush()
+}
+
+/// Check that the decoded data may be written to `path`.  Symbolic
+/// links are never followed, nor is anything but a regular file
+/// replaced.  A pathname taken from the input, rather than from -o,
+/// may not climb out of its directory with "..".
+fn check_target(path: &Path, from_input: bool) -> io::Result<()> {
+    if from_input && path.components().any(|c| c == Component::ParentDir) {
+        return Err(io::Error::other(gettext(
+            "pathname in input contains '..'; use -o to name the output",
+        )));
+    }
+
+    match fs::symlink_metadata(path) {
+        Ok(md) if md.file_type().is_symlink() => Err(io::Error::other(gettext(
+            "output file is a symbolic link; not overwriting",
+        ))),
+        Ok(md) if !md.is_file() => Err(io::Error::other(gettext(
+            "output file is not a regular file; not overwriting",
+        ))),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Create a new, private file beside `path`, to decode into.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, fs::File)> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let base = path.file_name().unwrap_or_default().to_string_lossy();
+
+    let mut attempt = 0;
+    loop {
+        let temp = dir.join(format!(
+            ".{}.uudecode.{}.{}",
+            base,
+            std::process::id(),
+            attempt
+        ));
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temp)
+        {
+            Ok(f) => return Ok((temp, f)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Decode into a temporary file, which replaces the target only once
+/// the whole body has decoded cleanly.
+fn decode_to_file(
+    header: &Header,
+    input: &mut dyn BufRead,
+    path: &Path,
+    from_input: bool,
+) -> io::Result<()> {
+    check_target(path, from_input)?;
+
+    let (temp, file) = create_temp(path)?;
+    let mut out = io::BufWriter::new(file);
+    let res = decode_body(header, input, &mut out)
+        .and_then(|_| {
+            let file = out.into_inner().map_err(|e| e.into_error())?;
+            file.set_permissions(fs::Permissions::from_mode(header.mode))
+        })
+        .and_then(|_| fs::rename(&temp, path));
+
+    if res.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    res
+}
+
+fn annotate(name: &str, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", name, e))
 }
 
 fn decode_file(args: &Args) -> io::Result<()> {
-    let mut file: Box<dyn Read>;
+    let input_name = args.file.as_deref().unwrap_or("stdin");
+    let file: Box<dyn Read>;
     if let Some(filename) = &args.file {
-        file = Box::new(fs::File::open(filename)?);
+        file = Box::new(fs::File::open(filename).map_err(|e| annotate(filename, e))?);
     } else {
         file = Box::new(io::stdin().lock());
     }
+    let mut input = BufReader::new(file);
 
-    // read entire file into memory.
-    // ugly but necessary due to uudecode crate implementation.
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer)?;
+    let header = find_header(&mut input).map_err(|e| annotate(input_name, e))?;
 
-    // attempt base64 decode
-    let b64_res = BASE64_STANDARD.decode(buffer.as_bytes());
-    match b64_res {
-        // fall through to uudecode format
-        Err(_e) => {}
+    let (pathname, from_input) = match &args.outfile {
+        Some(outfile) => (outfile.as_str(), false),
+        None => (header.pathname.as_str(), true),
+    };
 
-        // decode succeeded. exit here.
-        Ok(bindata) => match &args.outfile {
-            None => return write_file("bindata.out", &bindata[..]),
-            Some(outfn) => return write_file(outfn, &bindata[..]),
-        },
-    }
-
-    // attempt to decode using uudecode format
-    match uuencode::uudecode(&buffer) {
-        None => return Err(Error::new(ErrorKind::Other, "invalid input data")),
-        Some((bindata, filename)) => match &args.outfile {
-            None => write_file(&filename, &bindata[..]),
-            Some(outfn) => write_file(outfn, &bindata[..]),
-        },
+    if pathname == "/dev/stdout" || pathname == "-" {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        decode_body(&header, &mut input, &mut out).map_err(|e| annotate(input_name, e))
+    } else {
+        decode_to_file(&header, &mut input, Path::new(pathname), from_input)
+            .map_err(|e| annotate(pathname, e))
     }
 }
 
@@ -94,7 +307,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if let Err(e) = decode_file(&args) {
         exit_code = 1;
-        eprintln!("{:?}: {}", args.file, e);
+        eprintln!("uudecode: {}", e);
     }
 
     std::process::exit(exit_code)
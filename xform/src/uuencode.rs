@@ -9,7 +9,6 @@
 
 extern crate clap;
 extern crate plib;
-extern crate uuencode;
 
 use base64::prelude::*;
 use clap::Parser;
@@ -17,6 +16,11 @@ use gettextrs::{bind_textdomain_codeset, textdomain};
 use plib::PROJECT_NAME;
 use std::fs;
 use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+
+// bytes of input encoded on each output line, giving 60 characters
+// of encoded data in either format
+const LINE_BYTES: usize = 45;
 
 /// uuencode - encode a binary file
 #[derive(Parser, Debug)]
@@ -26,45 +30,112 @@ struct Args {
     #[arg(short = 'm', long)]
     base64: bool,
 
-    /// File to read as input.
-    file: Option<String>,
+    /// File to read as input (default: stdin; "-" also means stdin), then
+    /// the pathname to which uudecode will write the decoded data.
+    #[arg(value_name = "[FILE] DECODE_PATH", required = true, num_args = 1..=2)]
+    operands: Vec<String>,
+}
+
+/// Encode one historical-format character.  A zero is written as '`',
+/// rather than a space, so that lines do not end in blanks.
+fn uu_char(c: u8) -> u8 {
+    match c & 0o77 {
+        0 => b'`',
+        c => c + b' ',
+    }
+}
 
-    /// Decode pathname
-    decode_path: Option<String>,
+/// Encode one line of the historical format: a length character, then
+/// each group of three bytes as four characters.
+fn uu_line(data: &[u8], line: &mut Vec<u8>) {
+    line.clear();
+    line.push(uu_char(data.len() as u8));
+    for group in data.chunks(3) {
+        let b0 = group[0];
+        let b1 = group.get(1).copied().unwrap_or(0);
+        let b2 = group.get(2).copied().unwrap_or(0);
+        line.push(uu_char(b0 >> 2));
+        line.push(uu_char((b0 << 4) | (b1 >> 4)));
+        line.push(uu_char((b1 << 2) | (b2 >> 6)));
+        line.push(uu_char(b2));
+    }
+    line.push(b'\n');
+}
+
+/// Fill `buf` from the input, short only at the end of the input.
+fn read_line_bytes(file: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match file.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
 }
 
 fn encode_file(args: &Args) -> io::Result<()> {
+    let (filename, decode_path) = match args.operands.as_slice() {
+        [decode_path] => (None, decode_path),
+        [filename, decode_path] if filename == "-" => (None, decode_path),
+        [filename, decode_path] => (Some(filename), decode_path),
+        _ => unreachable!(),
+    };
+
+    // the mode is that of the input file, or for stdin, that of a new
+    // file created under the current umask
     let mut file: Box<dyn Read>;
-    if let Some(filename) = &args.file {
-        file = Box::new(fs::File::open(filename)?);
+    let mode;
+    if let Some(filename) = filename {
+        let f = fs::File::open(filename)?;
+        mode = f.metadata()?.permissions().mode() & 0o777;
+        file = Box::new(f);
     } else {
+        let umask = unsafe {
+            let mask = libc::umask(0);
+            libc::umask(mask);
+            mask
+        };
+        mode = 0o666 & !(umask as u32);
         file = Box::new(io::stdin().lock());
     }
 
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    if args.base64 {
+        writeln!(out, "begin-base64 {:o} {}", mode, decode_path)?;
+    } else {
+        writeln!(out, "begin {:o} {}", mode, decode_path)?;
+    }
 
-    let decode_path;
-    match &args.decode_path {
-        None => {
-            decode_path = String::from("/dev/stdout");
+    let mut buffer = [0; LINE_BYTES];
+    let mut line = Vec::with_capacity(LINE_BYTES * 2);
+    loop {
+        let n = read_line_bytes(&mut file, &mut buffer)?;
+        if n == 0 {
+            break;
         }
-        Some(path) => {
-            decode_path = String::from(path);
+
+        if args.base64 {
+            out.write_all(BASE64_STANDARD.encode(&buffer[..n]).as_bytes())?;
+            out.write_all(b"\n")?;
+        } else {
+            uu_line(&buffer[..n], &mut line);
+            out.write_all(&line)?;
+        }
+
+        if n < LINE_BYTES {
+            break;
         }
     }
 
-    let output;
     if args.base64 {
-        output = BASE64_STANDARD.encode(&buffer[..]);
+        out.write_all(b"====\n")?;
     } else {
-        output = uuencode::uuencode(&decode_path, &buffer[..]);
+        out.write_all(b"`\nend\n")?;
     }
-
-    io::stdout().write_all(output.as_bytes())?;
-    io::stdout().write_all(b"\n")?;
-
-    Ok(())
+    out.flush()
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -78,7 +149,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if let Err(e) = encode_file(&args) {
         exit_code = 1;
-        eprintln!("{:?}: {}", args.file, e);
+        let name = if args.operands.len() > 1 && args.operands[0] != "-" {
+            &args.operands[0]
+        } else {
+            "stdin"
+        };
+        eprintln!("uuencode: {}: {}", name, e);
     }
 
     std::process::exit(exit_code)
@@ -0,0 +1,84 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+
+extern crate clap;
+extern crate plib;
+
+// the encoder half is used by compress
+#[allow(dead_code)]
+mod lzw;
+
+use clap::Parser;
+use gettextrs::{bind_textdomain_codeset, textdomain};
+use lzw::UnixLZWReader;
+use plib::PROJECT_NAME;
+use std::fs;
+use std::io::{self, Read, Write};
+
+/// zcat - expand and concatenate data
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about)]
+struct Args {
+    /// Files to expand; ".Z" is added to names that lack it.  Use "-" or no-args for stdin.
+    files: Vec<String>,
+}
+
+fn zcat_file(filename: &str) -> io::Result<()> {
+    let file: Box<dyn Read>;
+    if filename.is_empty() || filename == "-" {
+        file = Box::new(io::stdin().lock());
+    } else if filename.ends_with(".Z") {
+        file = Box::new(fs::File::open(filename)?);
+    } else {
+        file = Box::new(fs::File::open(format!("{}.Z", filename))?);
+    }
+
+    let mut decoder = UnixLZWReader::new(file);
+    let mut out = io::stdout().lock();
+
+    loop {
+        let buf = decoder.read()?;
+        if buf.is_empty() {
+            break;
+        }
+
+        out.write_all(&buf)?;
+    }
+
+    out.flush()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // parse command line arguments
+    let mut args = Args::parse();
+
+    textdomain(PROJECT_NAME)?;
+    bind_textdomain_codeset(PROJECT_NAME, "UTF-8")?;
+
+    // if no file args, read from stdin
+    if args.files.is_empty() {
+        args.files.push(String::new());
+    }
+
+    let mut exit_code = 0;
+
+    for filename in &args.files {
+        if let Err(e) = zcat_file(filename) {
+            exit_code = 1;
+            let name = if filename.is_empty() {
+                "stdin"
+            } else {
+                filename
+            };
+            eprintln!("zcat: {}: {}", name, e);
+        }
+    }
+
+    std::process::exit(exit_code)
+}
@@ -0,0 +1,59 @@
+//
+// Copyright (c) 2024 Jeff Garzik
+//
+// This file is part of the posixutils-rs project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+//
+// File handling shared by compress and uncompress, which both replace
+// each named file with its counterpart.
+//
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+
+/// Ask a yes/no question on stderr.  A failure to read the answer
+/// counts as "no".
+pub fn prompt_user(prompt: &str) -> bool {
+    eprint!("{} ", prompt);
+    let mut response = String::new();
+    match io::stdin().read_line(&mut response) {
+        Ok(_) => response.to_lowercase().starts_with('y'),
+        Err(_) => false,
+    }
+}
+
+/// Give `file` the mode, ownership and times of the original.
+pub fn copy_metadata(md: &fs::Metadata, file: &fs::File) -> io::Result<()> {
+    // ownership may only be kept where allowed, and without it, the
+    // setuid and setgid bits are dropped
+    let mut mode = md.mode() & 0o7777;
+    if std::os::unix::fs::fchown(file, Some(md.uid()), Some(md.gid())).is_err() {
+        mode &= !0o6000;
+    }
+    file.set_permissions(fs::Permissions::from_mode(mode))?;
+
+    let times = fs::FileTimes::new()
+        .set_accessed(md.accessed()?)
+        .set_modified(md.modified()?);
+    file.set_times(times)
+}
+
+/// Create `path` afresh for the replacement file.  Whatever is there
+/// is removed first, so that a symbolic link is replaced rather than
+/// followed; should anything reappear in the meantime, creation fails.
+pub fn create_output(path: &str) -> io::Result<fs::File> {
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
@@ -8,6 +8,10 @@
 //
 
 use plib::{run_test, TestPlan};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Output, Stdio};
 
 fn cksum_test(test_data: &str, expected_output: &str) {
     run_test(TestPlan {
@@ -15,6 +19,7 @@ fn cksum_test(test_data: &str, expected_output: &str) {
         args: Vec::new(),
         stdin_data: String::from(test_data),
         expected_out: String::from(expected_output),
+        expected_exit_code: 0,
     });
 }
 
@@ -22,3 +27,315 @@ fn cksum_test(test_data: &str, expected_output: &str) {
 fn test_cksum() {
     cksum_test("foo\n", "3915528286 4\n");
 }
+
+fn cksum_args_test(args: &[&str], test_data: &str, expected_output: &str, expected_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("cksum"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::from(test_data),
+        expected_out: String::from(expected_output),
+        expected_exit_code: expected_code,
+    });
+}
+
+#[test]
+fn test_cksum_algorithms() {
+    cksum_args_test(
+        &["-a", "md5"],
+        "foo\n",
+        "MD5 (-) = d3b07384d113edec49eaa6238ad5ff00\n",
+        0,
+    );
+    cksum_args_test(
+        &["-a", "sha256", "--untagged"],
+        "foo\n",
+        "b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c  -\n",
+        0,
+    );
+}
+
+#[test]
+fn test_cksum_check() {
+    cksum_args_test(
+        &["-c"],
+        "MD5 (/dev/null) = d41d8cd98f00b204e9800998ecf8427e\n",
+        "/dev/null: OK\n",
+        0,
+    );
+    cksum_args_test(
+        &["-c", "-a", "sha1"],
+        "da39a3ee5e6b4b0d3255bfef95601890afd80709  /dev/null\n\
+         0000000000000000000000000000000000000000  /dev/null\n",
+        "/dev/null: OK\n/dev/null: FAILED\n",
+        1,
+    );
+}
+
+fn uuencode_test(args: &[&str], expected_output: &str) {
+    run_test(TestPlan {
+        cmd: String::from("uuencode"),
+        args: args.iter().map(|s| String::from(*s)).collect(),
+        stdin_data: String::new(),
+        expected_out: String::from(expected_output),
+        expected_exit_code: 0,
+    });
+}
+
+#[test]
+fn test_uuencode_empty() {
+    uuencode_test(&["/dev/null", "out.bin"], "begin 666 out.bin\n`\nend\n");
+    uuencode_test(
+        &["-m", "/dev/null", "out.bin"],
+        "begin-base64 666 out.bin\n====\n",
+    );
+}
+
+#[test]
+fn test_uuencode_stdin_operand() {
+    let umask = unsafe {
+        let mask = libc::umask(0);
+        libc::umask(mask);
+        mask
+    };
+    let mode = 0o666 & !(umask as u32);
+
+    // "-" reads the standard input, with the mode of a new file
+    let output = run_filter("uuencode", &["-", "out.bin"], b"foo\n");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!("begin {:o} out.bin\n$9F]O\"@``\n`\nend\n", mode)
+    );
+}
+
+fn uudecode_test(test_data: &str, expected_output: &str, expected_code: i32) {
+    run_test(TestPlan {
+        cmd: String::from("uudecode"),
+        args: Vec::new(),
+        stdin_data: String::from(test_data),
+        expected_out: String::from(expected_output),
+        expected_exit_code: expected_code,
+    });
+}
+
+#[test]
+fn test_uudecode_stdout() {
+    uudecode_test(
+        "Subject: data\n\nbegin 644 /dev/stdout\n$9F]O\"@``\n`\nend\n",
+        "foo\n",
+        0,
+    );
+    uudecode_test(
+        "begin-base64 644 /dev/stdout\nZm9v\nCg==\n====\n",
+        "foo\n",
+        0,
+    );
+    uudecode_test("no header here\n", "", 1);
+}
+
+fn run_filter_output(cmd: &str, args: &[&str], input: &[u8]) -> Output {
+    let path = std::env::current_dir()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join(format!("target/release/{}", cmd));
+
+    let mut child = Command::new(path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // the command may fail before reading all of its input
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let feeder = std::thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output().unwrap();
+    feeder.join().unwrap();
+    output
+}
+
+fn run_filter(cmd: &str, args: &[&str], input: &[u8]) -> Vec<u8> {
+    let output = run_filter_output(cmd, args, input);
+    assert!(output.status.success());
+    output.stdout
+}
+
+fn compress_round_trip(data: &[u8], bits: &str) {
+    let compressed = run_filter("compress", &["-b", bits], data);
+    assert_eq!(&compressed[..2], &[0x1f, 0x9d]);
+    let restored = run_filter("uncompress", &[], &compressed);
+    assert_eq!(restored, data);
+    let restored = run_filter("zcat", &[], &compressed);
+    assert_eq!(restored, data);
+}
+
+#[test]
+fn test_compress_round_trip() {
+    let text: Vec<u8> = (0..200_000u32)
+        .flat_map(|i| format!("line {} of {}\n", i % 1000, i % 7).into_bytes())
+        .collect();
+    let mut x: u32 = 1;
+    let noise: Vec<u8> = (0..300_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect();
+
+    for bits in ["9", "12", "16"] {
+        compress_round_trip(b"", bits);
+        compress_round_trip(b"a", bits);
+        compress_round_trip(&text, bits);
+        compress_round_trip(&noise, bits);
+    }
+}
+
+#[test]
+fn test_compress_replaces_files() {
+    let dir = std::env::temp_dir().join(format!("xform-compress-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data");
+    let zpath = dir.join("data.Z");
+    let data = "compressible text\n".repeat(1000);
+    fs::write(&path, &data).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+    let bin = |cmd: &str| {
+        std::env::current_dir()
+            .unwrap()
+            .parent()
+            .unwrap()
+            .join(format!("target/release/{}", cmd))
+    };
+
+    let status = Command::new(bin("compress")).arg(&path).status().unwrap();
+    assert!(status.success());
+    assert!(!path.exists());
+    assert_eq!(
+        fs::metadata(&zpath).unwrap().permissions().mode() & 0o777,
+        0o640
+    );
+
+    // named without the suffix, as POSIX allows
+    let status = Command::new(bin("uncompress")).arg(&path).status().unwrap();
+    assert!(status.success());
+    assert!(!zpath.exists());
+    assert_eq!(fs::read_to_string(&path).unwrap(), data);
+    assert_eq!(
+        fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+        0o640
+    );
+
+    // a file that does not get smaller is left alone, with status 2
+    fs::write(&path, "x").unwrap();
+    let status = Command::new(bin("compress")).arg(&path).status().unwrap();
+    assert_eq!(status.code(), Some(2));
+    assert!(path.exists());
+    assert!(!zpath.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_zcat_truncated() {
+    let mut x: u32 = 1;
+    let noise: Vec<u8> = (0..300_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect();
+    let compressed = run_filter("compress", &[], &noise);
+
+    // a cut that splits one of the 16-bit codes, and one in the header
+    for cut in [compressed.len() / 2 + 1, 2] {
+        let output = run_filter_output("zcat", &[], &compressed[..cut]);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("unexpected end of file"));
+    }
+}
+
+#[test]
+fn test_uncompress_truncated_keeps_file() {
+    let dir = std::env::temp_dir().join(format!("xform-truncated-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data");
+    let zpath = dir.join("data.Z");
+
+    let data = "line of compressible text\n".repeat(2000);
+    let compressed = run_filter("compress", &[], data.as_bytes());
+    fs::write(&zpath, &compressed[..compressed.len() - 1]).unwrap();
+
+    let output = run_filter_output("uncompress", &[zpath.to_str().unwrap()], b"");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(zpath.exists());
+    assert!(!path.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_replace_symlink() {
+    let dir = std::env::temp_dir().join(format!("xform-symlink-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("data");
+    let zpath = dir.join("data.Z");
+    let victim = dir.join("victim");
+
+    let data = "compressible text\n".repeat(1000);
+    fs::write(&path, &data).unwrap();
+    fs::write(&victim, "keep").unwrap();
+    std::os::unix::fs::symlink(&victim, &zpath).unwrap();
+
+    // the link is replaced, not written through
+    let output = run_filter_output("compress", &["-f", path.to_str().unwrap()], b"");
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&victim).unwrap(), "keep");
+    assert!(fs::symlink_metadata(&zpath).unwrap().is_file());
+
+    // likewise for the expanded name
+    std::os::unix::fs::symlink(&victim, &path).unwrap();
+    let output = run_filter_output("uncompress", &["-f", zpath.to_str().unwrap()], b"");
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&victim).unwrap(), "keep");
+    assert_eq!(fs::read_to_string(&path).unwrap(), data);
+    assert!(fs::symlink_metadata(&path).unwrap().is_file());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_uudecode_absolute_path() {
+    let path = std::env::temp_dir().join(format!("uudecode-abs-{}", std::process::id()));
+    let encoded = format!("begin 644 {}\n$9F]O\"@``\n`\nend\n", path.display());
+
+    // an absolute pathname in the input is written to as given
+    let output = run_filter_output("uudecode", &[], encoded.as_bytes());
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "foo\n");
+
+    // but not through a symbolic link
+    fs::remove_file(&path).unwrap();
+    std::os::unix::fs::symlink("/dev/null", &path).unwrap();
+    let output = run_filter_output("uudecode", &[], encoded.as_bytes());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(fs::symlink_metadata(&path)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+
+    // a pathname that climbs with ".." is refused
+    let climbing = encoded.replace("begin 644 /", "begin 644 ../");
+    let output = run_filter_output("uudecode", &[], climbing.as_bytes());
+    assert_eq!(output.status.code(), Some(1));
+
+    fs::remove_file(&path).unwrap();
+}